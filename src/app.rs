@@ -0,0 +1,119 @@
+//! The service offered on top of QUIC connections.
+//!
+//! `EchoServer` handles packets, timers and connection state, and leaves
+//! stream and datagram data to an `Application`. Every connection gets its
+//! own instance, created by the `ApplicationFactory` registered on its
+//! listener for the ALPN the handshake settled on. Unless the listener is
+//! given applications of its own, `H3Server` serves `h3`, `HqFileServer` the
+//! HTTP/0.9 ALPNs when a `document_root` is configured, and `Echo` any other
+//! ALPN.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::ServerConfig;
+use crate::metrics::ServerMetrics;
+
+mod body;
+
+mod echo;
+pub use echo::Echo;
+
+mod h3;
+pub use self::h3::{H3Server, H3_ALPN};
+
+mod hq;
+pub use hq::{HqFileServer, HQ_ALPNS};
+
+/// Callbacks of a single connection.
+///
+/// They run while the connection's span is entered. Stream callbacks start
+/// once the handshake is complete, or earlier if 0-RTT data was accepted, so
+/// they may come before `on_established()`.
+pub trait Application: Send {
+    /// The handshake completed.
+    fn on_established(&mut self, _conn: &mut quiche::Connection) {}
+
+    /// Stream `stream_id` has data, or a FIN, to read.
+    fn on_stream_readable(&mut self, conn: &mut quiche::Connection, stream_id: u64);
+
+    /// Stream `stream_id` has room for more data.
+    fn on_stream_writable(&mut self, _conn: &mut quiche::Connection, _stream_id: u64) {}
+
+    /// A DATAGRAM frame was received.
+    fn on_datagram(&mut self, _conn: &mut quiche::Connection, _data: &[u8]) {}
+
+    /// The connection is closed and about to be dropped.
+    fn on_closed(&mut self, _conn: &quiche::Connection) {}
+}
+
+/// Creates the `Application` of each new connection.
+pub type ApplicationFactory = Arc<dyn Fn() -> Box<dyn Application> + Send + Sync>;
+
+/// Wraps a constructor of `A` into an `ApplicationFactory`.
+pub fn factory<A, F>(new_app: F) -> ApplicationFactory
+where
+    A: Application + 'static,
+    F: Fn() -> A + Send + Sync + 'static,
+{
+    Arc::new(move || Box::new(new_app()) as Box<dyn Application>)
+}
+
+/// Transport error closing connections whose ALPN has no application: the
+/// TLS `no_application_protocol` alert as a QUIC CRYPTO_ERROR.
+pub const NO_APPLICATION_PROTOCOL: u64 = 0x100 + 120;
+
+/// The application factories of a listener, by ALPN.
+#[derive(Clone, Default)]
+pub(crate) struct Routes {
+    by_alpn: HashMap<Vec<u8>, ApplicationFactory>,
+    fallback: Option<ApplicationFactory>,
+}
+
+impl Routes {
+    /// Routes `h3` to `H3Server`, the HTTP/0.9 ALPNs to `HqFileServer` if
+    /// `config` has a document root, and everything else to an `Echo`
+    /// counting its datagrams in `metrics`.
+    pub(crate) fn for_config(config: &ServerConfig, metrics: &Arc<ServerMetrics>) -> Routes {
+        let metrics = metrics.clone();
+        let mut routes = Routes {
+            by_alpn: HashMap::new(),
+            fallback: Some(factory(move || Echo::with_metrics(metrics.clone()))),
+        };
+
+        let root = config.document_root.clone().map(Arc::new);
+        if let Some(root) = root.clone() {
+            let hq = factory(move || HqFileServer::new(root.clone()));
+            for alpn in HQ_ALPNS {
+                routes.route(alpn, hq.clone());
+            }
+        }
+        routes.route(H3_ALPN, factory(move || H3Server::new(root.clone())));
+        routes
+    }
+
+    pub(crate) fn route(&mut self, alpn: &str, factory: ApplicationFactory) {
+        self.by_alpn.insert(alpn.as_bytes().to_vec(), factory);
+    }
+
+    /// Sets the factory used for ALPNs without a route of their own.
+    pub(crate) fn fallback(&mut self, factory: ApplicationFactory) {
+        self.fallback = Some(factory);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.by_alpn.is_empty() && self.fallback.is_none()
+    }
+
+    pub(crate) fn serves(&self, alpn: &[u8]) -> bool {
+        self.fallback.is_some() || self.by_alpn.contains_key(alpn)
+    }
+
+    /// Creates the application of a connection that negotiated `alpn`.
+    pub(crate) fn create(&self, alpn: &[u8]) -> Option<Box<dyn Application>> {
+        self.by_alpn
+            .get(alpn)
+            .or(self.fallback.as_ref())
+            .map(|factory| factory())
+    }
+}
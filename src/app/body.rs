@@ -0,0 +1,33 @@
+use std::fs::File;
+use std::io::{self, Read};
+
+/// File data read at once, and so buffered per stream.
+const READ_CHUNK: usize = 65536;
+
+/// Where a response body comes from.
+pub(super) enum Body {
+    File(File),
+    Static(&'static [u8]),
+    /// The body of the request on the same stream, which only the HTTP/3
+    /// layer can read.
+    Echo,
+    None,
+}
+
+impl Body {
+    /// Appends the next chunk of a file or static body to `buf`, and returns
+    /// its length, or 0 once the body is complete. An echo body has nothing
+    /// to read here.
+    pub(super) fn read_chunk(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        match self {
+            Body::File(file) => file.by_ref().take(READ_CHUNK as u64).read_to_end(buf),
+
+            Body::Static(data) => {
+                buf.extend_from_slice(data);
+                Ok(std::mem::take(data).len())
+            }
+
+            Body::Echo | Body::None => Ok(0),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tracing::{debug, trace};
+
+use super::Application;
+use crate::metrics::{Metric, ServerMetrics};
+
+/// Data buffered per stream above which the server stops reading from it, so
+/// that a peer which does not read its echo is throttled by flow control
+/// instead of growing the buffer.
+const MAX_PENDING_ECHO: usize = 65535;
+
+/// Bytes read from a stream at once.
+const READ_CHUNK: usize = 16384;
+
+/// Stream data received from the peer that flow control did not let us echo
+/// back yet.
+#[derive(Default)]
+struct PendingEcho {
+    buf: Vec<u8>,
+    /// The peer finished the stream, so our FIN follows `buf`.
+    fin: bool,
+}
+
+/// Sends stream data back on the stream it arrived on, finishing the stream
+/// once the peer has finished it, and DATAGRAM frames back as datagrams.
+#[derive(Default)]
+pub struct Echo {
+    pending: HashMap<u64, PendingEcho>,
+    metrics: Option<Arc<ServerMetrics>>,
+}
+
+impl Echo {
+    pub fn new() -> Echo {
+        Echo::default()
+    }
+
+    /// Counts the datagram echoes, and the ones dropped, in `metrics`.
+    pub fn with_metrics(metrics: Arc<ServerMetrics>) -> Echo {
+        Echo {
+            pending: HashMap::new(),
+            metrics: Some(metrics),
+        }
+    }
+
+    fn count(&self, metric: impl Fn(&ServerMetrics) -> &Metric) {
+        if let Some(metrics) = &self.metrics {
+            metric(metrics).inc();
+        }
+    }
+
+    /// Writes as much of the pending echo of stream `s` as flow control
+    /// allows.
+    fn echo_pending(&mut self, conn: &mut quiche::Connection, s: u64) {
+        let pending = match self.pending.get_mut(&s) {
+            Some(v) => v,
+
+            None => return,
+        };
+        if pending.buf.is_empty() && !pending.fin {
+            self.pending.remove(&s);
+            return;
+        }
+
+        // quiche only sends the FIN if the whole buffer fits.
+        let written = match conn.stream_send(s, &pending.buf, pending.fin) {
+            Ok(v) => v,
+
+            Err(quiche::Error::Done) => 0,
+
+            Err(e) => {
+                debug!(stream = s, error = ?e, "stream send failed");
+                self.pending.remove(&s);
+                return;
+            }
+        };
+        trace!(stream = s, bytes = written, "write into stream");
+
+        pending.buf.drain(..written);
+        if pending.buf.is_empty() && pending.fin {
+            self.pending.remove(&s);
+        }
+    }
+}
+
+impl Application for Echo {
+    fn on_stream_readable(&mut self, conn: &mut quiche::Connection, s: u64) {
+        loop {
+            let pending = self.pending.entry(s).or_default();
+            if pending.fin || pending.buf.len() >= MAX_PENDING_ECHO {
+                break;
+            }
+
+            // Read straight into the pending buffer, and cut it back to the
+            // data actually received.
+            let len = pending.buf.len();
+            pending.buf.resize(len + READ_CHUNK, 0);
+            let (read, fin) = match conn.stream_recv(s, &mut pending.buf[len..]) {
+                Ok(v) => v,
+
+                Err(quiche::Error::Done) => {
+                    pending.buf.truncate(len);
+                    break;
+                }
+
+                Err(e) => {
+                    debug!(stream = s, error = ?e, "stream recv failed");
+                    self.pending.remove(&s);
+                    break;
+                }
+            };
+            trace!(stream = s, bytes = read, fin, "stream data received");
+
+            pending.buf.truncate(len + read);
+            pending.fin = fin;
+        }
+
+        self.echo_pending(conn, s);
+    }
+
+    /// Echoes what flow control held back.
+    fn on_stream_writable(&mut self, conn: &mut quiche::Connection, s: u64) {
+        self.echo_pending(conn, s);
+    }
+
+    /// Datagrams are unreliable, so an echo that does not fit into the send
+    /// queue, or into a DATAGRAM frame, is dropped rather than kept.
+    fn on_datagram(&mut self, conn: &mut quiche::Connection, data: &[u8]) {
+        match conn.dgram_send(data) {
+            Ok(()) => {
+                trace!(bytes = data.len(), "datagram echoed");
+                self.count(|m| &m.dgram_echoed);
+            }
+
+            Err(quiche::Error::Done) => {
+                debug!(bytes = data.len(), "datagram send queue full, echo dropped");
+                self.count(|m| &m.dgram_dropped_queue_full);
+            }
+
+            Err(quiche::Error::BufferTooShort) => {
+                debug!(bytes = data.len(), "datagram too large to echo, dropped");
+                self.count(|m| &m.dgram_dropped_oversize);
+            }
+
+            Err(e) => debug!(error = ?e, "datagram send failed"),
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use quiche::h3::{self, NameValue};
+use tracing::{debug, info, trace, warn};
+
+use super::body::Body;
+use super::hq::resolve;
+use super::Application;
+
+/// ALPN of HTTP/3.
+pub const H3_ALPN: &str = "h3";
+
+/// Request body data buffered per stream. Request bodies are not read further
+/// until their echo is written, so that flow control throttles the client.
+const ECHO_CHUNK: usize = 65536;
+
+/// A response being sent.
+struct Response {
+    /// Sent first, once the stream has room for them.
+    headers: Option<Vec<h3::Header>>,
+    body: Body,
+    /// Read from the body but not accepted by flow control yet.
+    buf: Vec<u8>,
+    /// The client finished the request stream.
+    request_finished: bool,
+}
+
+impl Response {
+    fn new(status: u16, extra: &[(&str, String)], body: Body) -> Response {
+        let mut headers = vec![
+            h3::Header::new(":status", &status.to_string()),
+            h3::Header::new("server", "quic_echo"),
+        ];
+        for (name, value) in extra {
+            headers.push(h3::Header::new(name, value));
+        }
+
+        Response {
+            headers: Some(headers),
+            body,
+            buf: Vec::new(),
+            request_finished: false,
+        }
+    }
+
+    /// Sends as much of the response on stream `s` as flow control allows,
+    /// and returns true once it is complete or failed.
+    fn progress(&mut self, h3: &mut h3::Connection, conn: &mut quiche::Connection, s: u64) -> bool {
+        if let Some(headers) = &self.headers {
+            let fin = matches!(self.body, Body::None);
+            match h3.send_response(conn, s, headers, fin) {
+                Ok(()) => self.headers = None,
+
+                Err(h3::Error::StreamBlocked) => return false,
+
+                Err(e) => {
+                    debug!(stream = s, error = ?e, "sending response headers failed");
+                    return true;
+                }
+            }
+            if fin {
+                return true;
+            }
+        }
+
+        loop {
+            if self.buf.is_empty() {
+                let read = match &mut self.body {
+                    Body::Echo => {
+                        self.buf.resize(ECHO_CHUNK, 0);
+                        let read = h3.recv_body(conn, s, &mut self.buf);
+                        self.buf.truncate(*read.as_ref().unwrap_or(&0));
+                        match read {
+                            Ok(v) => Ok(v),
+
+                            // Wait for more of the request, or for its end.
+                            Err(h3::Error::Done) if !self.request_finished => return false,
+
+                            Err(h3::Error::Done) => Ok(0),
+
+                            Err(e) => {
+                                debug!(stream = s, error = ?e, "reading request body failed");
+                                return true;
+                            }
+                        }
+                    }
+
+                    body => body.read_chunk(&mut self.buf),
+                };
+
+                match read {
+                    // The whole body is sent, finish the stream.
+                    Ok(0) => {
+                        return match h3.send_body(conn, s, &[], true) {
+                            Ok(_) => true,
+
+                            Err(h3::Error::Done) => false,
+
+                            Err(e) => {
+                                debug!(stream = s, error = ?e, "finishing response failed");
+                                true
+                            }
+                        };
+                    }
+
+                    Ok(_) => (),
+
+                    Err(e) => {
+                        debug!(stream = s, error = ?e, "reading the file failed");
+                        conn.stream_shutdown(s, quiche::Shutdown::Write, 0).ok();
+                        return true;
+                    }
+                }
+            }
+
+            let written = match h3.send_body(conn, s, &self.buf, false) {
+                Ok(v) => v,
+
+                Err(h3::Error::Done) => 0,
+
+                Err(e) => {
+                    debug!(stream = s, error = ?e, "sending response body failed");
+                    return true;
+                }
+            };
+            trace!(stream = s, bytes = written, "response body written");
+
+            self.buf.drain(..written);
+            if !self.buf.is_empty() {
+                return false;
+            }
+        }
+    }
+}
+
+/// Serves HTTP/3: `GET` requests get the files below the document root, and
+/// `POST /echo` gets its request body back as the response body.
+///
+/// Without a document root, every `GET` gets a 404.
+pub struct H3Server {
+    root: Option<Arc<PathBuf>>,
+    h3: Option<h3::Connection>,
+    responses: HashMap<u64, Response>,
+}
+
+impl H3Server {
+    pub fn new(root: Option<Arc<PathBuf>>) -> H3Server {
+        H3Server {
+            root,
+            h3: None,
+            responses: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of responses that are not complete yet.
+    pub fn responses_in_progress(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// Sets up HTTP/3 on top of `conn`, unless already done.
+    fn start(&mut self, conn: &mut quiche::Connection) -> bool {
+        if self.h3.is_some() {
+            return true;
+        }
+
+        let config = match h3::Config::new() {
+            Ok(v) => v,
+
+            Err(e) => {
+                warn!(error = ?e, "creating the HTTP/3 config failed");
+                return false;
+            }
+        };
+        match h3::Connection::with_transport(conn, &config) {
+            Ok(v) => {
+                self.h3 = Some(v);
+                true
+            }
+
+            Err(e) => {
+                warn!(error = ?e, "starting HTTP/3 failed");
+                conn.close(true, 0x101, b"HTTP/3 setup failed").ok();
+                false
+            }
+        }
+    }
+
+    /// Handles everything the HTTP/3 layer has to report.
+    fn poll(&mut self, conn: &mut quiche::Connection) {
+        if !self.start(conn) {
+            return;
+        }
+        let h3 = match self.h3.as_mut() {
+            Some(v) => v,
+
+            None => return,
+        };
+
+        loop {
+            let s = match h3.poll(conn) {
+                Ok((s, h3::Event::Headers { list, .. })) => {
+                    let response = respond(self.root.as_deref(), &list);
+                    self.responses.insert(s, response);
+                    s
+                }
+
+                Ok((s, h3::Event::Data)) => {
+                    let consumed = self
+                        .responses
+                        .get(&s)
+                        .map_or(false, |r| matches!(r.body, Body::Echo));
+                    if !consumed {
+                        discard_body(h3, conn, s);
+                        continue;
+                    }
+                    s
+                }
+
+                Ok((s, h3::Event::Finished)) => {
+                    if let Some(response) = self.responses.get_mut(&s) {
+                        response.request_finished = true;
+                    }
+                    s
+                }
+
+                // The request is abandoned, and so is its response.
+                Ok((s, h3::Event::Reset(e))) => {
+                    debug!(stream = s, error = e, "request reset");
+                    if self.responses.remove(&s).is_some() {
+                        conn.stream_shutdown(s, quiche::Shutdown::Write, 0).ok();
+                    }
+                    continue;
+                }
+
+                Ok(_) => continue,
+
+                Err(h3::Error::Done) => break,
+
+                Err(e) => {
+                    debug!(error = ?e, "HTTP/3 poll failed");
+                    break;
+                }
+            };
+
+            if let Some(response) = self.responses.get_mut(&s) {
+                if response.progress(h3, conn, s) {
+                    self.responses.remove(&s);
+                }
+            }
+        }
+    }
+}
+
+/// Reads and drops the request body on stream `s`, which no response uses, so
+/// that it does not hold on to flow control credit of the connection.
+fn discard_body(h3: &mut h3::Connection, conn: &mut quiche::Connection, s: u64) {
+    let mut buf = [0; 4096];
+    while let Ok(read) = h3.recv_body(conn, s, &mut buf) {
+        if read == 0 {
+            break;
+        }
+        trace!(stream = s, bytes = read, "request body discarded");
+    }
+}
+
+/// Picks the response to a request with `headers`.
+fn respond(root: Option<&PathBuf>, headers: &[h3::Header]) -> Response {
+    let mut method = None;
+    let mut path = None;
+    for header in headers {
+        match header.name() {
+            ":method" => method = Some(header.value()),
+
+            ":path" => path = Some(header.value()),
+
+            _ => (),
+        }
+    }
+
+    let (method, path) = match (method, path) {
+        (Some(method), Some(path)) => (method, path),
+
+        _ => {
+            debug!("request without :method or :path");
+            return Response::new(400, &[], Body::None);
+        }
+    };
+    info!(method, path, "request");
+
+    match method {
+        "GET" => {
+            let file = root
+                .and_then(|root| resolve(root, path))
+                .and_then(|p| File::open(p).ok());
+            match file {
+                Some(file) => {
+                    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+                    let extra = [("content-length", len.to_string())];
+                    Response::new(200, &extra, Body::File(file))
+                }
+
+                None => Response::new(404, &[], Body::None),
+            }
+        }
+
+        "POST" if path == "/echo" => Response::new(200, &[], Body::Echo),
+
+        "POST" => Response::new(404, &[], Body::None),
+
+        _ => {
+            let extra = [("allow", "GET, POST".to_string())];
+            Response::new(405, &extra, Body::None)
+        }
+    }
+}
+
+impl Application for H3Server {
+    fn on_established(&mut self, conn: &mut quiche::Connection) {
+        self.start(conn);
+    }
+
+    /// The HTTP/3 layer reads the streams itself.
+    fn on_stream_readable(&mut self, conn: &mut quiche::Connection, _stream_id: u64) {
+        self.poll(conn);
+    }
+
+    fn on_stream_writable(&mut self, conn: &mut quiche::Connection, s: u64) {
+        let h3 = match self.h3.as_mut() {
+            Some(v) => v,
+
+            None => return,
+        };
+        if let Some(response) = self.responses.get_mut(&s) {
+            if response.progress(h3, conn, s) {
+                self.responses.remove(&s);
+            }
+        }
+    }
+}
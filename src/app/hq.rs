@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use tracing::{debug, info, trace};
+
+use super::body::Body;
+use super::Application;
+
+/// ALPNs of HTTP/0.9 over QUIC, as used by the interop runner and its drafts.
+pub const HQ_ALPNS: [&str; 5] = ["hq-interop", "hq-29", "hq-28", "hq-27", "http/0.9"];
+
+/// Longest request line accepted.
+const MAX_REQUEST: usize = 8192;
+
+const NOT_FOUND: &[u8] = b"Not Found!\r\n";
+const BAD_REQUEST: &[u8] = b"Bad Request!\r\n";
+
+/// A request being received, or its response being sent.
+enum HqStream {
+    Request(Vec<u8>),
+    Response {
+        body: Body,
+        /// Body data waiting for room in the stream.
+        buf: Vec<u8>,
+    },
+    /// The response is done, the rest of the request, up to its FIN, is
+    /// dropped.
+    Done,
+}
+
+/// Serves the files below a document root over HTTP/0.9: a request is a
+/// `GET /path` line on a bidirectional stream, and the response is the
+/// content of the file, finished with the stream.
+///
+/// A request for a directory gets its `index.html`. Missing files, and paths
+/// leading out of the document root, get a "Not Found!" body, as HTTP/0.9 has
+/// no status codes.
+pub struct HqFileServer {
+    root: Arc<PathBuf>,
+    streams: HashMap<u64, HqStream>,
+}
+
+impl HqFileServer {
+    pub fn new(root: Arc<PathBuf>) -> HqFileServer {
+        HqFileServer {
+            root,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of streams whose request or response is not
+    /// complete yet.
+    pub fn streams_in_progress(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Reads the request on stream `s`, and returns it once it is complete.
+    fn read_request(&mut self, conn: &mut quiche::Connection, s: u64) -> Option<Vec<u8>> {
+        let request = match self
+            .streams
+            .entry(s)
+            .or_insert(HqStream::Request(Vec::new()))
+        {
+            HqStream::Request(v) => v,
+
+            // The rest of the stream does not matter.
+            HqStream::Response { .. } => {
+                drain(conn, s);
+                return None;
+            }
+
+            HqStream::Done => {
+                drain(conn, s);
+                if conn.stream_finished(s) {
+                    self.streams.remove(&s);
+                }
+                return None;
+            }
+        };
+
+        let mut buf = [0; 1024];
+        loop {
+            let (read, fin) = match conn.stream_recv(s, &mut buf) {
+                Ok(v) => v,
+
+                Err(quiche::Error::Done) => return None,
+
+                Err(e) => {
+                    debug!(stream = s, error = ?e, "stream recv failed");
+                    self.streams.remove(&s);
+                    return None;
+                }
+            };
+            request.extend_from_slice(&buf[..read]);
+
+            if fin || request.contains(&b'\n') || request.len() > MAX_REQUEST {
+                return Some(std::mem::take(request));
+            }
+        }
+    }
+
+    /// Sends as much of the response on stream `s` as flow control allows.
+    fn send_response(&mut self, conn: &mut quiche::Connection, s: u64) {
+        let (body, buf) = match self.streams.get_mut(&s) {
+            Some(HqStream::Response { body, buf }) => (body, buf),
+
+            _ => return,
+        };
+
+        loop {
+            if buf.is_empty() {
+                match body.read_chunk(buf) {
+                    // The whole body is sent, finish the stream.
+                    Ok(0) => {
+                        if let Err(e) = conn.stream_send(s, &[], true) {
+                            if e == quiche::Error::Done {
+                                return;
+                            }
+                            debug!(stream = s, error = ?e, "stream send failed");
+                        }
+                        self.complete(conn, s);
+                        return;
+                    }
+
+                    Ok(_) => (),
+
+                    Err(e) => {
+                        debug!(stream = s, error = ?e, "reading the file failed");
+                        conn.stream_shutdown(s, quiche::Shutdown::Write, 0).ok();
+                        self.complete(conn, s);
+                        return;
+                    }
+                }
+            }
+
+            let written = match conn.stream_send(s, buf, false) {
+                Ok(v) => v,
+
+                Err(quiche::Error::Done) => 0,
+
+                Err(e) => {
+                    debug!(stream = s, error = ?e, "stream send failed");
+                    self.complete(conn, s);
+                    return;
+                }
+            };
+            trace!(stream = s, bytes = written, "write into stream");
+
+            buf.drain(..written);
+            if !buf.is_empty() {
+                return;
+            }
+        }
+    }
+
+    /// Forgets stream `s` once its response is done, unless the request
+    /// still has to be read up to its FIN, which must not start a new
+    /// request.
+    fn complete(&mut self, conn: &quiche::Connection, s: u64) {
+        if conn.stream_finished(s) {
+            self.streams.remove(&s);
+        } else {
+            self.streams.insert(s, HqStream::Done);
+        }
+    }
+
+    /// Opens the file answering `request`.
+    fn respond(&self, request: &[u8]) -> Body {
+        let line = String::from_utf8_lossy(request);
+        let line = line.lines().next().unwrap_or("");
+
+        let mut parts = line.split_whitespace();
+        let path = match (parts.next(), parts.next()) {
+            (Some("GET"), Some(path)) => path,
+
+            _ => {
+                debug!(request = %line, "bad request");
+                return Body::Static(BAD_REQUEST);
+            }
+        };
+
+        match resolve(&self.root, path).and_then(|p| File::open(p).ok()) {
+            Some(file) => {
+                info!(path, "serving file");
+                Body::File(file)
+            }
+
+            None => {
+                info!(path, "file not found");
+                Body::Static(NOT_FOUND)
+            }
+        }
+    }
+}
+
+/// Reads and drops what stream `s` has to read.
+fn drain(conn: &mut quiche::Connection, s: u64) {
+    let mut buf = [0; 1024];
+    while conn.stream_recv(s, &mut buf).is_ok() {}
+}
+
+/// Maps the path of a request to a file below `root`, or to None if it
+/// leads elsewhere or to nothing.
+pub(super) fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    if !path.starts_with('/') {
+        return None;
+    }
+
+    let mut file = root.to_path_buf();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        // Only plain names, so that `..`, drive letters and separators of
+        // other platforms cannot climb out of the root.
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) if !segment.contains('\\') => file.push(name),
+
+            _ => return None,
+        }
+    }
+    if file.is_dir() {
+        file.push("index.html");
+    }
+
+    // Symbolic links must not lead out of the root either.
+    let root = root.canonicalize().ok()?;
+    let file = file.canonicalize().ok()?;
+    if !file.starts_with(&root) || !file.is_file() {
+        return None;
+    }
+    Some(file)
+}
+
+impl Application for HqFileServer {
+    fn on_stream_readable(&mut self, conn: &mut quiche::Connection, s: u64) {
+        // Responses go out on the request stream, which the client opens.
+        if s % 4 != 0 {
+            debug!(stream = s, "ignoring stream");
+            drain(conn, s);
+            return;
+        }
+
+        let request = match self.read_request(conn, s) {
+            Some(v) => v,
+
+            None => return,
+        };
+
+        let body = self.respond(&request);
+        self.streams.insert(
+            s,
+            HqStream::Response {
+                body,
+                buf: Vec::new(),
+            },
+        );
+        self.send_response(conn, s);
+    }
+
+    fn on_stream_writable(&mut self, conn: &mut quiche::Connection, s: u64) {
+        self.send_response(conn, s);
+    }
+}
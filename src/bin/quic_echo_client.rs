@@ -0,0 +1,477 @@
+//! Connects to a QUIC echo server, sends the same payload on a number of
+//! bidirectional streams, checks that each stream echoes it back unchanged,
+//! and reports how long that took.
+
+use std::fs;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use ring::rand::{SecureRandom, SystemRandom};
+use tracing::{debug, warn};
+use tracing_subscriber::util::SubscriberInitExt;
+
+use quic_echo::logging::{self, LogFormat};
+
+/// Largest UDP payload sent or received, as on the server.
+const MAX_DATAGRAM_SIZE: usize = 1350;
+
+/// QUIC echo client.
+#[derive(Parser, Debug)]
+#[clap(version, about)]
+struct Args {
+    /// Server to connect to, as HOST:PORT.
+    #[clap(default_value = "127.0.0.1:4443")]
+    server: String,
+
+    /// Number of bidirectional streams, each carrying the whole payload.
+    #[clap(long, value_name = "COUNT", default_value = "1")]
+    streams: u64,
+
+    /// Send the content of this file.
+    #[clap(long, value_name = "FILE", conflicts_with = "stdin")]
+    file: Option<PathBuf>,
+
+    /// Send what is read from standard input, up to its end.
+    #[clap(long)]
+    stdin: bool,
+
+    /// Size of the payload generated when neither --file nor --stdin is
+    /// given.
+    #[clap(long, value_name = "BYTES", default_value = "1048576")]
+    size: usize,
+
+    /// Application protocol offered during the handshake.
+    #[clap(long, value_name = "PROTO", default_value = "sample")]
+    alpn: String,
+
+    /// Verify the server certificate against the CA certificates in this PEM
+    /// file. Without it, any certificate is accepted.
+    #[clap(long, value_name = "FILE")]
+    ca: Option<PathBuf>,
+
+    /// Idle timeout in milliseconds.
+    #[clap(long, value_name = "MS", default_value = "5000")]
+    idle_timeout: u64,
+
+    /// Log filter, e.g. `debug` or `quiche=trace`. Defaults to RUST_LOG, or
+    /// `info` if that is not set.
+    #[clap(long, value_name = "FILTER")]
+    log: Option<String>,
+
+    /// Log output: text or json.
+    #[clap(long, value_name = "FORMAT", default_value = "text")]
+    log_format: LogFormat,
+}
+
+impl Args {
+    fn payload(&self) -> Result<Vec<u8>, String> {
+        if let Some(path) = &self.file {
+            return fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e));
+        }
+
+        if self.stdin {
+            let mut payload = Vec::new();
+            io::stdin()
+                .read_to_end(&mut payload)
+                .map_err(|e| format!("cannot read stdin: {}", e))?;
+            return Ok(payload);
+        }
+
+        Ok((0..self.size).map(|i| (i % 251) as u8).collect())
+    }
+
+    fn quiche_config(&self) -> Result<quiche::Config, String> {
+        if self.alpn.is_empty() || self.alpn.len() > 255 {
+            return Err(format!(
+                "alpn {:?} must be between 1 and 255 bytes long",
+                self.alpn
+            ));
+        }
+        let mut alpn = vec![self.alpn.len() as u8];
+        alpn.extend_from_slice(self.alpn.as_bytes());
+
+        let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)
+            .map_err(|e| format!("creating the QUIC config failed: {:?}", e))?;
+        config
+            .set_application_protos(&alpn)
+            .map_err(|e| format!("invalid alpn: {:?}", e))?;
+        config.set_max_idle_timeout(self.idle_timeout);
+        config.set_max_recv_udp_payload_size(MAX_DATAGRAM_SIZE);
+        config.set_max_send_udp_payload_size(MAX_DATAGRAM_SIZE);
+        config.set_initial_max_data(10_000_000);
+        config.set_initial_max_stream_data_bidi_local(1_000_000);
+        config.set_disable_active_migration(true);
+
+        match &self.ca {
+            Some(ca) => {
+                let path = ca
+                    .to_str()
+                    .ok_or_else(|| format!("{} is not valid UTF-8", ca.display()))?;
+                config
+                    .load_verify_locations_from_file(path)
+                    .map_err(|e| format!("loading {} failed: {:?}", ca.display(), e))?;
+                config.verify_peer(true);
+            }
+
+            None => config.verify_peer(false),
+        }
+
+        Ok(config)
+    }
+}
+
+/// The host part of `server`, unless it is an IP address.
+fn server_name(server: &str) -> Option<&str> {
+    let host = server.rsplit_once(':').map_or(server, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() || host.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+    Some(host)
+}
+
+/// Progress of a single stream.
+#[derive(Default)]
+struct EchoStream {
+    /// Payload bytes accepted by flow control.
+    sent: usize,
+    fin_sent: bool,
+    /// Echoed bytes received so far.
+    received: usize,
+    /// Nothing more is expected on the stream.
+    finished: bool,
+    /// Why the echo does not count, if it does not.
+    error: Option<String>,
+}
+
+impl EchoStream {
+    /// Records the first thing that went wrong.
+    fn fail(&mut self, s: u64, error: String) {
+        if self.error.is_none() {
+            warn!(stream = s, %error, "echo failed");
+            self.error = Some(error);
+        }
+    }
+
+    fn verified(&self) -> bool {
+        self.finished && self.error.is_none()
+    }
+}
+
+struct Client<'a> {
+    conn: Pin<Box<quiche::Connection>>,
+    socket: UdpSocket,
+    payload: &'a [u8],
+    streams: Vec<EchoStream>,
+    buf: [u8; 65535],
+    out: [u8; MAX_DATAGRAM_SIZE],
+}
+
+impl Client<'_> {
+    /// Sends every packet quiche has ready.
+    fn flush(&mut self) -> Result<(), String> {
+        loop {
+            let (write, send_info) = match self.conn.send(&mut self.out) {
+                Ok(v) => v,
+
+                Err(quiche::Error::Done) => return Ok(()),
+
+                Err(e) => return Err(format!("sending failed: {:?}", e)),
+            };
+
+            if let Err(e) = self.socket.send_to(&self.out[..write], send_info.to) {
+                if e.kind() == io::ErrorKind::WouldBlock {
+                    debug!("socket busy, leaving the packet to loss recovery");
+                    return Ok(());
+                }
+                return Err(format!("send_to() failed: {}", e));
+            }
+        }
+    }
+
+    /// Waits for a packet until the next timer expires, then takes every
+    /// other packet that already arrived.
+    fn recv(&mut self) -> Result<(), String> {
+        // std refuses a zero read timeout.
+        let timeout = self.conn.timeout().map(|t| t.max(Duration::from_millis(1)));
+        self.socket
+            .set_nonblocking(false)
+            .and_then(|_| self.socket.set_read_timeout(timeout))
+            .map_err(|e| format!("configuring the socket failed: {}", e))?;
+
+        let mut first = true;
+        loop {
+            let (read, from) = match self.socket.recv_from(&mut self.buf) {
+                Ok(v) => v,
+
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    if first {
+                        self.conn.on_timeout();
+                    }
+                    return Ok(());
+                }
+
+                Err(e) => return Err(format!("recv_from() failed: {}", e)),
+            };
+
+            let recv_info = quiche::RecvInfo { from };
+            if let Err(e) = self.conn.recv(&mut self.buf[..read], recv_info) {
+                debug!(error = ?e, "packet dropped");
+            }
+
+            if first {
+                first = false;
+                self.socket
+                    .set_nonblocking(true)
+                    .map_err(|e| format!("configuring the socket failed: {}", e))?;
+            }
+        }
+    }
+
+    /// Writes as much of the payload as flow control allows, opening the
+    /// streams in order.
+    fn send_streams(&mut self) {
+        for (i, stream) in self.streams.iter_mut().enumerate() {
+            if stream.fin_sent || stream.finished {
+                continue;
+            }
+
+            let s = i as u64 * 4;
+            match self.conn.stream_send(s, &self.payload[stream.sent..], true) {
+                Ok(written) => {
+                    stream.sent += written;
+                    stream.fin_sent = stream.sent == self.payload.len();
+                }
+
+                Err(quiche::Error::Done) => (),
+
+                // The server allows more once the earlier streams are done.
+                Err(quiche::Error::StreamLimit) => break,
+
+                Err(e) => {
+                    stream.fail(s, format!("stream send failed: {:?}", e));
+                    stream.finished = true;
+                }
+            }
+        }
+    }
+
+    /// Reads the echoes and compares them with the payload.
+    fn read_streams(&mut self) {
+        for s in self.conn.readable() {
+            let stream = match self.streams.get_mut((s / 4) as usize) {
+                Some(v) if s % 4 == 0 => v,
+
+                _ => {
+                    debug!(stream = s, "ignoring stream");
+                    continue;
+                }
+            };
+
+            loop {
+                let (read, fin) = match self.conn.stream_recv(s, &mut self.buf) {
+                    Ok(v) => v,
+
+                    Err(quiche::Error::Done) => break,
+
+                    Err(e) => {
+                        stream.fail(s, format!("stream recv failed: {:?}", e));
+                        stream.finished = true;
+                        break;
+                    }
+                };
+
+                let expected = self.payload.get(stream.received..stream.received + read);
+                if expected != Some(&self.buf[..read]) {
+                    let error = format!(
+                        "echo differs from the payload at offset {}",
+                        stream.received
+                    );
+                    stream.fail(s, error);
+                }
+                stream.received += read;
+
+                if fin {
+                    if stream.received != self.payload.len() {
+                        let error = format!(
+                            "echo has {} bytes, the payload {}",
+                            stream.received,
+                            self.payload.len()
+                        );
+                        stream.fail(s, error);
+                    }
+                    stream.finished = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// What a run measured.
+struct Report {
+    handshake: Option<Duration>,
+    transfer: Option<Duration>,
+    rtt: Duration,
+    verified: usize,
+    echoed: usize,
+    /// Why the connection ended before every echo was complete.
+    close_reason: Option<String>,
+}
+
+fn run(args: &Args, payload: &[u8]) -> Result<Report, String> {
+    let peer = args
+        .server
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", args.server, e))?
+        .next()
+        .ok_or_else(|| format!("{} has no address", args.server))?;
+    let local: SocketAddr = if peer.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(local).map_err(|e| format!("binding {}: {}", local, e))?;
+
+    let mut scid = [0; quiche::MAX_CONN_ID_LEN];
+    SystemRandom::new()
+        .fill(&mut scid)
+        .map_err(|_| "generating the connection ID failed".to_string())?;
+    let scid = quiche::ConnectionId::from_ref(&scid);
+
+    let mut config = args.quiche_config()?;
+    let start = Instant::now();
+    let conn = quiche::connect(server_name(&args.server), &scid, peer, &mut config)
+        .map_err(|e| format!("connecting to {} failed: {:?}", peer, e))?;
+
+    let mut client = Client {
+        conn,
+        socket,
+        payload,
+        streams: (0..args.streams).map(|_| EchoStream::default()).collect(),
+        buf: [0; 65535],
+        out: [0; MAX_DATAGRAM_SIZE],
+    };
+
+    let mut handshake = None;
+    let mut transfer = None;
+    loop {
+        client.flush()?;
+        if client.conn.is_closed() {
+            break;
+        }
+        client.recv()?;
+
+        if !client.conn.is_established() || transfer.is_some() {
+            continue;
+        }
+        if handshake.is_none() {
+            handshake = Some(start.elapsed());
+            debug!(alpn = %String::from_utf8_lossy(client.conn.application_proto()), "connected");
+        }
+
+        client.read_streams();
+        client.send_streams();
+        if client.streams.iter().all(|s| s.finished) {
+            transfer = Some(start.elapsed() - handshake.unwrap_or_default());
+            client.conn.close(true, 0, b"").ok();
+        }
+    }
+
+    let close_reason = if transfer.is_some() {
+        None
+    } else if let Some(e) = client.conn.peer_error() {
+        Some(format!(
+            "closed by the server: error {:#x}, {}",
+            e.error_code,
+            String::from_utf8_lossy(&e.reason)
+        ))
+    } else if client.conn.is_timed_out() {
+        Some("idle timeout".to_string())
+    } else {
+        Some("connection closed".to_string())
+    };
+
+    Ok(Report {
+        handshake,
+        transfer,
+        rtt: client.conn.stats().rtt,
+        verified: client.streams.iter().filter(|s| s.verified()).count(),
+        echoed: client.streams.iter().map(|s| s.received).sum(),
+        close_reason,
+    })
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn main() {
+    let args = Args::parse();
+
+    // The report goes to stdout.
+    let logger = logging::filter(args.log.as_deref())
+        .map(|filter| logging::subscriber(args.log_format, filter, io::stderr))
+        .and_then(|s| {
+            s.try_init()
+                .map_err(|e| format!("cannot install the logger: {}", e))
+        });
+    if let Err(e) = logger {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let payload = match args.payload() {
+        Ok(v) => v,
+
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let report = match run(&args, &payload) {
+        Ok(v) => v,
+
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match report.handshake {
+        Some(d) => println!("handshake:  {:.3} ms", millis(d)),
+
+        None => println!("handshake:  failed"),
+    }
+    println!("rtt:        {:.3} ms", millis(report.rtt));
+    println!(
+        "streams:    {} of {} verified, {} bytes each",
+        report.verified,
+        args.streams,
+        payload.len()
+    );
+    if let Some(transfer) = report.transfer {
+        let secs = transfer.as_secs_f64().max(f64::EPSILON);
+        println!(
+            "throughput: {:.2} Mbit/s, {} bytes echoed in {:.3} ms",
+            report.echoed as f64 * 8.0 / secs / 1e6,
+            report.echoed,
+            millis(transfer)
+        );
+    }
+    if let Some(reason) = &report.close_reason {
+        println!("closed:     {}", reason);
+    }
+
+    if report.verified as u64 != args.streams {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,151 @@
+//! Records describing closed connections, for offline analysis.
+//!
+//! When a connection is collected, the server builds a `CloseRecord` from its
+//! statistics and close reason and hands it to its `CloseSink`, if it has one.
+//! `JsonLines` writes the records as one JSON object per line.
+
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Which side ended a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseInitiator {
+    /// The server sent a CONNECTION_CLOSE.
+    Local,
+    /// The client sent a CONNECTION_CLOSE.
+    Peer,
+    /// Nothing was heard from the client for the idle timeout.
+    IdleTimeout,
+}
+
+/// Summary of a connection, emitted once it is closed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CloseRecord {
+    pub trace_id: String,
+    pub listener: SocketAddr,
+    pub peer: SocketAddr,
+    /// Negotiated application protocol, empty if the handshake did not get
+    /// that far.
+    pub alpn: String,
+    pub established: bool,
+    /// Time from the first Initial packet to the collection of the
+    /// connection.
+    pub duration_ms: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+    pub packets_retransmitted: u64,
+    /// Smoothed round-trip time, in milliseconds.
+    pub rtt_ms: f64,
+    pub closed_by: CloseInitiator,
+    /// Whether `error_code` is an application error rather than a transport
+    /// error.
+    pub app_error: bool,
+    pub error_code: Option<u64>,
+    /// Reason phrase of the CONNECTION_CLOSE, lossily decoded as UTF-8.
+    pub reason: String,
+}
+
+impl CloseRecord {
+    /// Describes `conn`, which must be closed.
+    pub(crate) fn new(
+        conn: &quiche::Connection,
+        listener: SocketAddr,
+        peer: SocketAddr,
+        established: bool,
+        duration: Duration,
+    ) -> CloseRecord {
+        let stats = conn.stats();
+
+        let (closed_by, error) = if let Some(e) = conn.peer_error() {
+            (CloseInitiator::Peer, Some(e))
+        } else if let Some(e) = conn.local_error() {
+            (CloseInitiator::Local, Some(e))
+        } else if conn.is_timed_out() {
+            (CloseInitiator::IdleTimeout, None)
+        } else {
+            (CloseInitiator::Local, None)
+        };
+
+        CloseRecord {
+            trace_id: conn.trace_id().to_string(),
+            listener,
+            peer,
+            alpn: String::from_utf8_lossy(conn.application_proto()).into_owned(),
+            established,
+            duration_ms: duration.as_millis() as u64,
+            bytes_sent: stats.sent_bytes,
+            bytes_received: stats.recv_bytes,
+            packets_sent: stats.sent as u64,
+            packets_received: stats.recv as u64,
+            packets_lost: stats.lost as u64,
+            packets_retransmitted: stats.retrans as u64,
+            rtt_ms: stats.rtt.as_secs_f64() * 1000.0,
+            closed_by,
+            app_error: error.map_or(false, |e| e.is_app),
+            error_code: error.map(|e| e.error_code),
+            reason: error
+                .map(|e| String::from_utf8_lossy(&e.reason).into_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Destination of the records of closed connections.
+pub trait CloseSink {
+    fn record(&mut self, record: &CloseRecord) -> io::Result<()>;
+}
+
+/// Writes each record as a JSON object on its own line.
+pub struct JsonLines<W> {
+    writer: W,
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(writer: W) -> JsonLines<W> {
+        JsonLines { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl JsonLines<std::fs::File> {
+    /// Appends to the file at `path`, creating it if missing.
+    ///
+    /// Every record is written with a single call, so several servers can
+    /// share a file.
+    pub fn append(path: &Path) -> io::Result<JsonLines<std::fs::File>> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(JsonLines::new(file))
+    }
+}
+
+impl<W: Write> CloseSink for JsonLines<W> {
+    fn record(&mut self, record: &CloseRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.writer.flush()
+    }
+}
+
+/// Passes the records to another thread.
+impl CloseSink for mpsc::Sender<CloseRecord> {
+    fn record(&mut self, record: &CloseRecord) -> io::Result<()> {
+        self.send(record.clone())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "receiver dropped"))
+    }
+}
@@ -0,0 +1,499 @@
+//! Server settings, either assembled in code or loaded from a TOML file.
+//!
+//! A configuration file holds one `[[listener]]` section per server:
+//!
+//! ```toml
+//! [[listener]]
+//! listen = "[::]:4443"
+//! dual_stack = true
+//! cert = "cert.crt"
+//! key = "cert.key"
+//! alpn = ["hq-interop", "sample"]
+//! idle_timeout = 5000
+//! max_data = 10000000
+//! max_streams_bidi = 100
+//! retry_token_lifetime = 10
+//! retry_token_rotation = 3600
+//! retry = "under-load"
+//! retry_max_half_open = 100
+//! retry_max_initial_rate = 10
+//! send_queue_capacity = 1024
+//! qlog_dir = "qlog"
+//! close_log = "closed.jsonl"
+//! shutdown_error_code = 0
+//! document_root = "www"
+//! enable_dgram = true
+//! dgram_recv_queue_len = 1000
+//! dgram_send_queue_len = 1000
+//! ```
+//!
+//! `listen`, `cert` and `key` are mandatory, every other key defaults to the
+//! value in `ServerConfig::default()`. `dual_stack` makes an IPv6 listener
+//! accept IPv4 clients too, and defaults to false. Without `qlog_dir`, no
+//! qlog is written, and without `close_log`, closed connections are not
+//! recorded. With `document_root`, the HTTP/0.9 ALPNs and HTTP/3 `GET`
+//! requests serve files from that directory. `enable_dgram` lets clients send
+//! QUIC DATAGRAM frames, which are echoed back. Relative paths are resolved
+//! against the directory containing the configuration file.
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::retry::RetryPolicy;
+use crate::{EchoServerError, EchoServerResult};
+
+/// Settings of a single `EchoServer`.
+///
+/// The defaults match the values the server used to hard-code, except that
+/// `application_protos` now offers `h3` first, followed by the HTTP/0.9 ALPNs.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// PEM file containing the certificate chain.
+    pub cert_chain: PathBuf,
+    /// PEM file containing the private key.
+    pub priv_key: PathBuf,
+    /// Application protocols offered during the handshake, in order of
+    /// preference.
+    pub application_protos: Vec<String>,
+    /// Idle timeout in milliseconds.
+    pub idle_timeout: u64,
+    /// Maximum UDP payload size, for both sending and receiving.
+    pub max_udp_payload_size: usize,
+    pub max_data: u64,
+    pub max_stream_data_bidi_local: u64,
+    pub max_stream_data_bidi_remote: u64,
+    pub max_stream_data_uni: u64,
+    pub max_streams_bidi: u64,
+    pub max_streams_uni: u64,
+    pub early_data: bool,
+    /// How long a stateless retry token stays valid.
+    pub retry_token_lifetime: Duration,
+    /// How often the key sealing retry tokens is replaced. Must not be shorter
+    /// than `retry_token_lifetime`.
+    pub retry_token_rotation: Duration,
+    /// When new connections are answered with a Retry.
+    pub retry_policy: RetryPolicy,
+    /// Number of half-open connections above which `RetryPolicy::UnderLoad`
+    /// starts retrying.
+    pub retry_max_half_open: usize,
+    /// Initial packets per second from a single IP address above which
+    /// `RetryPolicy::UnderLoad` starts retrying.
+    pub retry_max_initial_rate: u32,
+    /// Number of outbound datagrams kept while the transport is busy.
+    pub send_queue_capacity: usize,
+    /// Directory receiving a qlog file per connection, named after its trace
+    /// ID. Created if missing.
+    pub qlog_dir: Option<PathBuf>,
+    /// JSON-lines file receiving a `close_log::CloseRecord` per closed
+    /// connection. Appended to, and created if missing.
+    pub close_log: Option<PathBuf>,
+    /// Application error code of the CONNECTION_CLOSE sent to every client
+    /// when the server shuts down.
+    pub shutdown_error_code: u64,
+    /// Directory served to the HTTP/0.9 (hq-interop) ALPNs and to HTTP/3
+    /// `GET` requests. Without it, the HTTP/0.9 ALPNs echo.
+    pub document_root: Option<PathBuf>,
+    /// Accept QUIC DATAGRAM frames (RFC 9221), and echo them back.
+    pub enable_dgram: bool,
+    /// Received datagrams kept per connection until they are read. Further
+    /// datagrams replace the oldest ones.
+    pub dgram_recv_queue_len: usize,
+    /// Datagrams kept per connection until they are sent. Echoes that do not
+    /// fit are dropped.
+    pub dgram_send_queue_len: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            cert_chain: PathBuf::from("src/cert.crt"),
+            priv_key: PathBuf::from("src/cert.key"),
+            application_protos: [
+                "h3",
+                "hq-interop",
+                "hq-29",
+                "hq-28",
+                "hq-27",
+                "http/0.9",
+                "sample",
+            ]
+            .iter()
+            .map(|p| p.to_string())
+            .collect(),
+            idle_timeout: 5000,
+            max_udp_payload_size: 1350,
+            max_data: 10_000_000,
+            max_stream_data_bidi_local: 1_000_000,
+            max_stream_data_bidi_remote: 1_000_000,
+            max_stream_data_uni: 1_000_000,
+            max_streams_bidi: 100,
+            max_streams_uni: 100,
+            early_data: true,
+            retry_token_lifetime: Duration::from_secs(10),
+            retry_token_rotation: Duration::from_secs(3600),
+            retry_policy: RetryPolicy::Always,
+            retry_max_half_open: 100,
+            retry_max_initial_rate: 10,
+            send_queue_capacity: 1024,
+            qlog_dir: None,
+            close_log: None,
+            shutdown_error_code: 0,
+            document_root: None,
+            enable_dgram: false,
+            dgram_recv_queue_len: 1000,
+            dgram_send_queue_len: 1000,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Builds the quiche configuration shared by all connections of a server.
+    pub fn quiche_config(&self) -> EchoServerResult<quiche::Config> {
+        let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)
+            .map_err(|e| EchoServerError::Quiche("creating the quiche config".to_string(), e))?;
+        config
+            .load_cert_chain_from_pem_file(path_to_str(&self.cert_chain)?)
+            .map_err(|e| {
+                EchoServerError::Quiche(
+                    format!("loading certificate chain {}", self.cert_chain.display()),
+                    e,
+                )
+            })?;
+        config
+            .load_priv_key_from_pem_file(path_to_str(&self.priv_key)?)
+            .map_err(|e| {
+                EchoServerError::Quiche(
+                    format!("loading private key {}", self.priv_key.display()),
+                    e,
+                )
+            })?;
+
+        config
+            .set_application_protos(&alpn_wire_format(&self.application_protos)?)
+            .map_err(|e| EchoServerError::Quiche("setting application protocols".to_string(), e))?;
+
+        config.set_max_idle_timeout(self.idle_timeout);
+        config.set_max_recv_udp_payload_size(self.max_udp_payload_size);
+        config.set_max_send_udp_payload_size(self.max_udp_payload_size);
+        config.set_initial_max_data(self.max_data);
+        config.set_initial_max_stream_data_bidi_local(self.max_stream_data_bidi_local);
+        config.set_initial_max_stream_data_bidi_remote(self.max_stream_data_bidi_remote);
+        config.set_initial_max_stream_data_uni(self.max_stream_data_uni);
+        config.set_initial_max_streams_bidi(self.max_streams_bidi);
+        config.set_initial_max_streams_uni(self.max_streams_uni);
+        config.set_disable_active_migration(true);
+        if self.early_data {
+            config.enable_early_data();
+        }
+        config.enable_dgram(
+            self.enable_dgram,
+            self.dgram_recv_queue_len,
+            self.dgram_send_queue_len,
+        );
+
+        Ok(config)
+    }
+
+    /// Checks the settings that quiche would otherwise reject, or only reject
+    /// once the first connection arrives.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, path) in [("cert", &self.cert_chain), ("key", &self.priv_key)] {
+            if !path.is_file() {
+                return Err(format!("{} file {} does not exist", name, path.display()));
+            }
+        }
+
+        if self.application_protos.is_empty() {
+            return Err("alpn must not be empty".to_string());
+        }
+        for proto in &self.application_protos {
+            if proto.is_empty() || proto.len() > 255 {
+                return Err(format!(
+                    "alpn {:?} must be between 1 and 255 bytes long",
+                    proto
+                ));
+            }
+        }
+
+        if !(1200..=65527).contains(&self.max_udp_payload_size) {
+            return Err(format!(
+                "max_udp_payload_size {} is outside of 1200..=65527",
+                self.max_udp_payload_size
+            ));
+        }
+
+        if self.retry_token_rotation < self.retry_token_lifetime {
+            return Err(format!(
+                "retry_token_rotation ({}s) is shorter than retry_token_lifetime ({}s)",
+                self.retry_token_rotation.as_secs(),
+                self.retry_token_lifetime.as_secs()
+            ));
+        }
+
+        if self.send_queue_capacity == 0 {
+            return Err("send_queue_capacity must not be 0".to_string());
+        }
+
+        if self.enable_dgram && (self.dgram_recv_queue_len == 0 || self.dgram_send_queue_len == 0) {
+            return Err("dgram_recv_queue_len and dgram_send_queue_len must not be 0".to_string());
+        }
+
+        if let Some(root) = &self.document_root {
+            if !root.is_dir() {
+                return Err(format!(
+                    "document_root {} is not a directory",
+                    root.display()
+                ));
+            }
+        }
+
+        // Error codes are variable-length integers.
+        if self.shutdown_error_code >= 1 << 62 {
+            return Err(format!(
+                "shutdown_error_code {} does not fit in 62 bits",
+                self.shutdown_error_code
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Encodes a list of ALPN identifiers as length-prefixed strings.
+fn alpn_wire_format(protos: &[String]) -> EchoServerResult<Vec<u8>> {
+    let mut wire = Vec::new();
+    for proto in protos {
+        if proto.is_empty() || proto.len() > 255 {
+            return Err(
+                ConfigError::Invalid(format!("invalid ALPN identifier {:?}", proto)).into(),
+            );
+        }
+        wire.push(proto.len() as u8);
+        wire.extend_from_slice(proto.as_bytes());
+    }
+    Ok(wire)
+}
+
+fn path_to_str(path: &Path) -> EchoServerResult<&str> {
+    path.to_str().ok_or_else(|| {
+        ConfigError::Invalid(format!("{} is not valid UTF-8", path.display())).into()
+    })
+}
+
+/// A server bound to a specific address.
+#[derive(Clone, Debug)]
+pub struct ListenerConfig {
+    pub listen: SocketAddr,
+    /// Accept IPv4 clients on the IPv6 address `listen`.
+    pub dual_stack: bool,
+    pub server: ServerConfig,
+}
+
+impl ListenerConfig {
+    /// Tells whether both listeners would receive packets sent to the same
+    /// address.
+    fn overlaps(&self, other: &ListenerConfig) -> bool {
+        if self.listen == other.listen {
+            return true;
+        }
+        if self.listen.port() != other.listen.port() {
+            return false;
+        }
+
+        // A dual-stack wildcard socket also takes the IPv4 wildcard port.
+        let dual_wildcard = |l: &ListenerConfig| l.dual_stack && l.listen.ip().is_unspecified();
+        (dual_wildcard(self) && other.listen.is_ipv4())
+            || (dual_wildcard(other) && self.listen.is_ipv4())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read.
+    Io(PathBuf, io::Error),
+    /// The configuration file is not valid TOML or does not match the schema.
+    Parse(toml::de::Error),
+    /// A value is well-formed but unusable.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(e) => write!(f, "{}", e),
+            ConfigError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            ConfigError::Parse(e) => Some(e),
+            ConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    #[serde(default, rename = "listener")]
+    listeners: Vec<ListenerSection>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListenerSection {
+    listen: SocketAddr,
+    dual_stack: Option<bool>,
+    cert: PathBuf,
+    key: PathBuf,
+    alpn: Option<Vec<String>>,
+    idle_timeout: Option<u64>,
+    max_udp_payload_size: Option<usize>,
+    max_data: Option<u64>,
+    max_stream_data_bidi_local: Option<u64>,
+    max_stream_data_bidi_remote: Option<u64>,
+    max_stream_data_uni: Option<u64>,
+    max_streams_bidi: Option<u64>,
+    max_streams_uni: Option<u64>,
+    early_data: Option<bool>,
+    retry_token_lifetime: Option<u64>,
+    retry_token_rotation: Option<u64>,
+    retry: Option<RetryPolicy>,
+    retry_max_half_open: Option<usize>,
+    retry_max_initial_rate: Option<u32>,
+    send_queue_capacity: Option<usize>,
+    qlog_dir: Option<PathBuf>,
+    close_log: Option<PathBuf>,
+    shutdown_error_code: Option<u64>,
+    document_root: Option<PathBuf>,
+    enable_dgram: Option<bool>,
+    dgram_recv_queue_len: Option<usize>,
+    dgram_send_queue_len: Option<usize>,
+}
+
+impl ListenerSection {
+    fn into_listener_config(self, base_dir: &Path) -> ListenerConfig {
+        let default = ServerConfig::default();
+
+        ListenerConfig {
+            listen: self.listen,
+            dual_stack: self.dual_stack.unwrap_or(false),
+            server: ServerConfig {
+                cert_chain: base_dir.join(self.cert),
+                priv_key: base_dir.join(self.key),
+                application_protos: self.alpn.unwrap_or(default.application_protos),
+                idle_timeout: self.idle_timeout.unwrap_or(default.idle_timeout),
+                max_udp_payload_size: self
+                    .max_udp_payload_size
+                    .unwrap_or(default.max_udp_payload_size),
+                max_data: self.max_data.unwrap_or(default.max_data),
+                max_stream_data_bidi_local: self
+                    .max_stream_data_bidi_local
+                    .unwrap_or(default.max_stream_data_bidi_local),
+                max_stream_data_bidi_remote: self
+                    .max_stream_data_bidi_remote
+                    .unwrap_or(default.max_stream_data_bidi_remote),
+                max_stream_data_uni: self
+                    .max_stream_data_uni
+                    .unwrap_or(default.max_stream_data_uni),
+                max_streams_bidi: self.max_streams_bidi.unwrap_or(default.max_streams_bidi),
+                max_streams_uni: self.max_streams_uni.unwrap_or(default.max_streams_uni),
+                early_data: self.early_data.unwrap_or(default.early_data),
+                retry_token_lifetime: self
+                    .retry_token_lifetime
+                    .map(Duration::from_secs)
+                    .unwrap_or(default.retry_token_lifetime),
+                retry_token_rotation: self
+                    .retry_token_rotation
+                    .map(Duration::from_secs)
+                    .unwrap_or(default.retry_token_rotation),
+                retry_policy: self.retry.unwrap_or(default.retry_policy),
+                retry_max_half_open: self
+                    .retry_max_half_open
+                    .unwrap_or(default.retry_max_half_open),
+                retry_max_initial_rate: self
+                    .retry_max_initial_rate
+                    .unwrap_or(default.retry_max_initial_rate),
+                send_queue_capacity: self
+                    .send_queue_capacity
+                    .unwrap_or(default.send_queue_capacity),
+                qlog_dir: self.qlog_dir.map(|dir| base_dir.join(dir)),
+                close_log: self.close_log.map(|path| base_dir.join(path)),
+                shutdown_error_code: self
+                    .shutdown_error_code
+                    .unwrap_or(default.shutdown_error_code),
+                document_root: self.document_root.map(|dir| base_dir.join(dir)),
+                enable_dgram: self.enable_dgram.unwrap_or(default.enable_dgram),
+                dgram_recv_queue_len: self
+                    .dgram_recv_queue_len
+                    .unwrap_or(default.dgram_recv_queue_len),
+                dgram_send_queue_len: self
+                    .dgram_send_queue_len
+                    .unwrap_or(default.dgram_send_queue_len),
+            },
+        }
+    }
+}
+
+/// Loads and validates the listeners described by a TOML file.
+pub fn load_listeners(path: &Path) -> Result<Vec<ListenerConfig>, ConfigError> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    parse_listeners(&content, base_dir)
+}
+
+/// Parses and validates listeners from TOML text, resolving relative paths
+/// against `base_dir`.
+pub fn parse_listeners(content: &str, base_dir: &Path) -> Result<Vec<ListenerConfig>, ConfigError> {
+    let file: FileConfig = toml::from_str(content).map_err(ConfigError::Parse)?;
+    if file.listeners.is_empty() {
+        return Err(ConfigError::Invalid(
+            "no [[listener]] section found".to_string(),
+        ));
+    }
+
+    let listeners: Vec<ListenerConfig> = file
+        .listeners
+        .into_iter()
+        .map(|l| l.into_listener_config(base_dir))
+        .collect();
+
+    validate_listeners(&listeners)?;
+    Ok(listeners)
+}
+
+/// Checks that no two listeners share an address, and that each one is
+/// valid on its own.
+pub fn validate_listeners(listeners: &[ListenerConfig]) -> Result<(), ConfigError> {
+    for (i, listener) in listeners.iter().enumerate() {
+        if listeners[..i].iter().any(|l| l.overlaps(listener)) {
+            return Err(ConfigError::Invalid(format!(
+                "listener {}: address is used by more than one listener",
+                listener.listen
+            )));
+        }
+        if listener.dual_stack && listener.listen.is_ipv4() {
+            return Err(ConfigError::Invalid(format!(
+                "listener {}: dual_stack needs an IPv6 address",
+                listener.listen
+            )));
+        }
+        listener.server.validate().map_err(|msg| {
+            ConfigError::Invalid(format!("listener {}: {}", listener.listen, msg))
+        })?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+use std::io;
+
+use crate::config::ConfigError;
+
+/// Errors returned by `EchoServer` and the event loop.
+///
+/// The first two variants only concern a single packet, or ask the caller to
+/// try again later, and the server keeps running after them. The others are
+/// fatal, see `is_fatal()`.
+#[derive(Debug)]
+pub enum EchoServerError {
+    /// A received packet was dropped.
+    Discarded,
+    /// Packets wait in the send queue until the transport takes them.
+    SendPending,
+    /// An I/O operation failed.
+    Io(String, io::Error),
+    /// quiche failed outside of a connection, e.g. loading the certificate.
+    Quiche(String, quiche::Error),
+    /// The configuration is unusable.
+    Config(ConfigError),
+    /// Generating key material failed.
+    Crypto(String),
+}
+
+impl EchoServerError {
+    /// Tells whether the server, or the event loop, cannot continue.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            EchoServerError::Discarded | EchoServerError::SendPending
+        )
+    }
+}
+
+impl fmt::Display for EchoServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EchoServerError::Discarded => write!(f, "packet discarded"),
+            EchoServerError::SendPending => write!(f, "send pending"),
+            EchoServerError::Io(context, e) => write!(f, "{}: {}", context, e),
+            EchoServerError::Quiche(context, e) => write!(f, "{}: {}", context, e),
+            EchoServerError::Config(e) => write!(f, "{}", e),
+            EchoServerError::Crypto(context) => write!(f, "{}: key generation failed", context),
+        }
+    }
+}
+
+impl std::error::Error for EchoServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EchoServerError::Io(_, e) => Some(e),
+            EchoServerError::Quiche(_, e) => Some(e),
+            EchoServerError::Config(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for EchoServerError {
+    fn from(e: ConfigError) -> EchoServerError {
+        EchoServerError::Config(e)
+    }
+}
+
+pub type EchoServerResult<T> = std::result::Result<T, EchoServerError>;
@@ -0,0 +1,191 @@
+//! Drives any number of `EchoServer`s on the platform's native transport.
+//!
+//! The platform backend only reports which registered transport became
+//! readable or writable: mio readiness on unix, and the WinSock completion
+//! events on Windows. Dispatching readiness and connection timeouts to the
+//! servers is shared.
+//!
+//! A `ShutdownHandle` stops the loop from another thread, such as a signal
+//! handler: the servers close their connections, and `run()` returns once
+//! all of them are gone.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::transport::NativeTransport;
+use crate::{EchoServer, EchoServerError, EchoServerResult};
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+use self::unix::{Poller, Waker};
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+use self::windows::{Poller, Waker};
+
+/// Readiness of the transport registered with `token`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub token: usize,
+    pub readable: bool,
+    pub writable: bool,
+}
+
+/// Asks an `EventLoop` to shut down. Cloneable and usable from any thread.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    requested: Arc<AtomicBool>,
+    waker: Arc<Waker>,
+}
+
+impl ShutdownHandle {
+    /// Requests the shutdown and interrupts a pending `EventLoop::poll()`.
+    pub fn shutdown(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        if let Err(e) = self.waker.wake() {
+            warn!(error = ?e, "waking the event loop failed");
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// A set of servers and the poller watching their transports.
+pub struct EventLoop {
+    poller: Poller,
+    servers: Vec<EchoServer<NativeTransport>>,
+    events: Vec<Event>,
+    shutdown: ShutdownHandle,
+}
+
+impl EventLoop {
+    pub fn new() -> EchoServerResult<EventLoop> {
+        let poller =
+            Poller::new().map_err(|e| EchoServerError::Io("creating the poller".to_string(), e))?;
+        let shutdown = ShutdownHandle {
+            requested: Arc::new(AtomicBool::new(false)),
+            waker: poller.waker(),
+        };
+
+        Ok(EventLoop {
+            poller,
+            servers: Vec::new(),
+            events: Vec::new(),
+            shutdown,
+        })
+    }
+
+    /// Returns a handle stopping `run()`.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Adds a server to the loop, returning its index in `servers()`.
+    pub fn register(&mut self, mut server: EchoServer<NativeTransport>) -> EchoServerResult<usize> {
+        let token = self.servers.len();
+        self.poller
+            .register(server.transport(), token)
+            .map_err(|e| EchoServerError::Io(format!("registering server {}", token), e))?;
+
+        // Pick up anything that arrived before registration, and on Windows
+        // issue the first overlapped receive.
+        server.recv_quic_packets()?;
+
+        self.servers.push(server);
+        Ok(token)
+    }
+
+    pub fn servers(&self) -> &[EchoServer<NativeTransport>] {
+        &self.servers
+    }
+
+    pub fn servers_mut(&mut self) -> &mut [EchoServer<NativeTransport>] {
+        &mut self.servers
+    }
+
+    /// Runs the servers until a fatal error occurs, or until a shutdown is
+    /// requested and every connection has been closed.
+    pub fn run(&mut self) -> EchoServerResult<()> {
+        while !self.shutdown.is_requested() {
+            self.poll(None)?;
+        }
+
+        info!("shutdown requested, closing connections");
+        for server in self.servers.iter_mut() {
+            server.shutdown();
+        }
+
+        // Send the CONNECTION_CLOSE frames right away. The connections then
+        // wait for their closing or draining period, so that the peers learn
+        // about the close even if a packet is lost.
+        self.poll(Some(Duration::ZERO))?;
+        while self.servers.iter().any(|s| s.num_clients() > 0) {
+            self.poll(None)?;
+        }
+
+        info!("all connections closed");
+        Ok(())
+    }
+
+    /// Waits until a transport is ready or a connection times out, but no
+    /// longer than `max_wait`, and lets the servers handle what happened.
+    pub fn poll(&mut self, max_wait: Option<Duration>) -> EchoServerResult<()> {
+        let timeout = self
+            .servers
+            .iter_mut()
+            .filter_map(|s| s.timeout())
+            .chain(max_wait)
+            .min();
+
+        self.events.clear();
+        match self.poller.poll(&mut self.events, timeout) {
+            Ok(()) => (),
+
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+
+            Err(e) => {
+                return Err(EchoServerError::Io("poll()".to_string(), e));
+            }
+        }
+
+        for event in &self.events {
+            // Writability needs no handling of its own, queued packets are
+            // flushed below.
+            if event.readable {
+                self.servers[event.token].recv_quic_packets()?;
+            }
+        }
+
+        for server in self.servers.iter_mut() {
+            server.on_timeout();
+            match server.send_quic_packets() {
+                Err(e) if e.is_fatal() => return Err(e),
+
+                _ => (),
+            }
+            server.remove_closed_connections()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the servers until a fatal error occurs.
+///
+/// Use an `EventLoop` directly to be able to shut the servers down.
+pub fn run(servers: Vec<EchoServer<NativeTransport>>) -> EchoServerResult<()> {
+    let mut event_loop = EventLoop::new()?;
+    for server in servers {
+        event_loop.register(server)?;
+    }
+
+    event_loop.run()
+}
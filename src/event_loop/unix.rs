@@ -0,0 +1,67 @@
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::time::Duration;
+
+use mio::unix::SourceFd;
+use mio::{Events, Interest, Poll, Token};
+
+pub use mio::Waker;
+
+use super::Event;
+use crate::transport::NativeTransport;
+
+/// Readiness notification through mio.
+///
+/// Sources are registered edge-triggered, which is fine as servers always
+/// read and write until `WouldBlock`.
+pub struct Poller {
+    poll: Poll,
+    events: Events,
+    waker: Arc<Waker>,
+}
+
+/// Token of the waker, which no transport gets.
+const WAKE_TOKEN: Token = Token(usize::MAX);
+
+impl Poller {
+    pub fn new() -> io::Result<Poller> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKE_TOKEN)?);
+
+        Ok(Poller {
+            poll,
+            events: Events::with_capacity(1024),
+            waker,
+        })
+    }
+
+    /// Returns a waker interrupting `poll()` from any thread.
+    pub fn waker(&self) -> Arc<Waker> {
+        self.waker.clone()
+    }
+
+    pub fn register(&mut self, transport: &NativeTransport, token: usize) -> io::Result<()> {
+        self.poll.registry().register(
+            &mut SourceFd(&transport.as_raw_fd()),
+            Token(token),
+            Interest::READABLE | Interest::WRITABLE,
+        )
+    }
+
+    pub fn poll(&mut self, events: &mut Vec<Event>, timeout: Option<Duration>) -> io::Result<()> {
+        self.poll.poll(&mut self.events, timeout)?;
+
+        events.extend(
+            self.events
+                .iter()
+                .filter(|e| e.token() != WAKE_TOKEN)
+                .map(|e| Event {
+                    token: e.token().0,
+                    readable: e.is_readable(),
+                    writable: e.is_writable(),
+                }),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use winapi::shared::basetsd::ULONG_PTR;
+use winapi::shared::minwindef::{DWORD, FALSE};
+use winapi::shared::ntdef::{BOOLEAN, HANDLE, PVOID};
+use winapi::shared::winerror::WAIT_TIMEOUT;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::ioapiset::{
+    CreateIoCompletionPort, GetQueuedCompletionStatus, PostQueuedCompletionStatus,
+};
+use winapi::um::minwinbase::LPOVERLAPPED;
+use winapi::um::threadpoollegacyapiset::UnregisterWaitEx;
+use winapi::um::winbase::{RegisterWaitForSingleObject, INFINITE};
+use winapi::um::winnt::WT_EXECUTEINWAITTHREAD;
+
+use super::Event;
+use crate::transport::NativeTransport;
+
+/// An I/O completion port, closed once the poller and its wakers are gone.
+struct Port(HANDLE);
+
+// Completion ports may be used from any thread.
+unsafe impl Send for Port {}
+unsafe impl Sync for Port {}
+
+impl Drop for Port {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// Key of the wake-up notification, which no transport gets.
+const WAKE_KEY: ULONG_PTR = ULONG_PTR::MAX;
+
+/// Interrupts `Poller::poll()` from any thread.
+pub struct Waker {
+    port: Arc<Port>,
+}
+
+impl Waker {
+    pub fn wake(&self) -> io::Result<()> {
+        let ret =
+            unsafe { PostQueuedCompletionStatus(self.port.0, 0, WAKE_KEY, std::ptr::null_mut()) };
+        if ret == FALSE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// What a thread pool wait posts to the completion port once its event is
+/// signaled.
+struct WaitContext {
+    port: HANDLE,
+    key: ULONG_PTR,
+}
+
+/// Forwards the WinSock completion events to a single I/O completion port.
+///
+/// `WaitForMultipleObjects()` cannot wait on more than 64 handles, so each
+/// event is waited on by the system thread pool instead, which posts the
+/// event's key to the port. The events are auto-reset, so a registered wait
+/// fires once per completion.
+pub struct Poller {
+    port: Arc<Port>,
+    waits: Vec<(HANDLE, Box<WaitContext>)>,
+    waker: Arc<Waker>,
+}
+
+impl Poller {
+    pub fn new() -> io::Result<Poller> {
+        let port =
+            unsafe { CreateIoCompletionPort(INVALID_HANDLE_VALUE, std::ptr::null_mut(), 0, 1) };
+        if port.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        let port = Arc::new(Port(port));
+        Ok(Poller {
+            port: port.clone(),
+            waits: Vec::new(),
+            waker: Arc::new(Waker { port }),
+        })
+    }
+
+    /// Returns a waker interrupting `poll()` from any thread.
+    pub fn waker(&self) -> Arc<Waker> {
+        self.waker.clone()
+    }
+
+    pub fn register(&mut self, transport: &NativeTransport, token: usize) -> io::Result<()> {
+        // The key tells the receive event (even) from the send event (odd).
+        let events = [transport.recv_event(), transport.send_event()];
+        for (kind, event) in events.iter().enumerate() {
+            let context = Box::new(WaitContext {
+                port: self.port.0,
+                key: token * 2 + kind,
+            });
+
+            let mut wait = std::ptr::null_mut();
+            let ret = unsafe {
+                RegisterWaitForSingleObject(
+                    &mut wait,
+                    event.0 as HANDLE,
+                    Some(on_signaled),
+                    &*context as *const WaitContext as PVOID,
+                    INFINITE,
+                    WT_EXECUTEINWAITTHREAD,
+                )
+            };
+            if ret == FALSE {
+                return Err(io::Error::last_os_error());
+            }
+
+            self.waits.push((wait, context));
+        }
+        Ok(())
+    }
+
+    pub fn poll(&mut self, events: &mut Vec<Event>, timeout: Option<Duration>) -> io::Result<()> {
+        let mut timeout = match timeout {
+            Some(timeout) => timeout.as_millis().try_into().unwrap_or(INFINITE - 1),
+
+            None => INFINITE,
+        };
+
+        // Block for the first notification only, then take whatever else is
+        // already queued.
+        loop {
+            let mut transferred: DWORD = 0;
+            let mut key: ULONG_PTR = 0;
+            let mut overlapped: LPOVERLAPPED = std::ptr::null_mut();
+            let ret = unsafe {
+                GetQueuedCompletionStatus(
+                    self.port.0,
+                    &mut transferred,
+                    &mut key,
+                    &mut overlapped,
+                    timeout,
+                )
+            };
+            if ret == FALSE {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(WAIT_TIMEOUT as i32) {
+                    return Ok(());
+                }
+                return Err(err);
+            }
+
+            if key != WAKE_KEY {
+                events.push(Event {
+                    token: key / 2,
+                    readable: key % 2 == 0,
+                    writable: key % 2 == 1,
+                });
+            }
+            timeout = 0;
+        }
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        for (wait, _) in &self.waits {
+            // Waits for running callbacks, so the contexts can be freed.
+            unsafe { UnregisterWaitEx(*wait, INVALID_HANDLE_VALUE) };
+        }
+    }
+}
+
+unsafe extern "system" fn on_signaled(context: PVOID, _timed_out: BOOLEAN) {
+    let context = &*(context as *const WaitContext);
+    PostQueuedCompletionStatus(context.port, 0, context.key, std::ptr::null_mut());
+}
@@ -0,0 +1,36 @@
+//! A QUIC echo server built on quiche.
+//!
+//! An `EchoServer` is configured with an `EchoServerBuilder` and reads and
+//! writes packets through a `transport::DatagramTransport`, so it can run on
+//! WinSock, on a plain `std::net::UdpSocket` or on an in-memory loopback link.
+//!
+//! Each connection is served by the `app::Application` routed to the ALPN it
+//! negotiated. By default, `h3` gets HTTP/3, the HTTP/0.9 ALPNs get file
+//! serving when a `document_root` is configured, and every other ALPN echoes
+//! its stream data and datagrams. Applications set on the builder replace
+//! these routes: `alpn_application()` serves a single ALPN and
+//! `application()` every ALPN without one of its own. Connections negotiating
+//! an ALPN that neither covers are refused.
+//!
+//! The `quic_echo_client` binary checks a running server: it sends a payload
+//! on a number of streams and verifies that every stream echoes it back.
+
+pub mod app;
+pub mod close_log;
+pub mod config;
+pub use config::ServerConfig;
+
+mod error;
+pub use error::{EchoServerError, EchoServerResult};
+
+mod server;
+pub use server::{EchoServer, EchoServerBuilder};
+
+pub mod event_loop;
+pub mod logging;
+pub mod metrics;
+pub mod retry;
+pub mod send_queue;
+pub mod timer;
+pub mod token;
+pub mod transport;
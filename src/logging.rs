@@ -0,0 +1,96 @@
+//! Log output of the binaries.
+//!
+//! The library logs through `tracing`: per-packet events at the trace level,
+//! handshake details at debug, connections opening and closing at info and
+//! failures at warn. Events of a connection are emitted in a `conn` span
+//! carrying its `trace_id`, `listener` and `peer` address, other events in a
+//! `listener` span carrying its `addr`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// How log events are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One human-readable line per event.
+    Text,
+    /// One JSON object per line, with the fields of the current span.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}, expected text or json", s)),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Parses `tracing_subscriber::EnvFilter` directives such as `debug` or
+/// `quic_echo=trace,quiche=info`.
+///
+/// Without directives, `RUST_LOG` is used, and without `RUST_LOG` only info
+/// and above is logged.
+pub fn filter(directives: Option<&str>) -> Result<EnvFilter, String> {
+    let filter = match directives {
+        Some(directives) => EnvFilter::try_new(directives),
+
+        None => EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info")),
+    };
+    filter.map_err(|e| format!("invalid log filter: {}", e))
+}
+
+/// Builds a subscriber writing the events accepted by `filter` to
+/// `make_writer`.
+pub fn subscriber<W>(
+    format: LogFormat,
+    filter: EnvFilter,
+    make_writer: W,
+) -> Box<dyn tracing::Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    // Output is usually collected rather than watched, so no colors.
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(false)
+        .with_writer(make_writer);
+
+    match format {
+        LogFormat::Text => Box::new(builder.finish()),
+
+        LogFormat::Json => Box::new(
+            builder
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .finish(),
+        ),
+    }
+}
+
+/// Logs to stdout for the rest of the process, including the `log` records
+/// of quiche.
+pub fn init(format: LogFormat, directives: Option<&str>) -> Result<(), String> {
+    subscriber(format, filter(directives)?, std::io::stdout)
+        .try_init()
+        .map_err(|e| format!("cannot install the logger: {}", e))
+}
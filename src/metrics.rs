@@ -0,0 +1,309 @@
+//! Server counters and gauges, exposed in the Prometheus text format.
+//!
+//! Every `EchoServer` updates its own `ServerMetrics`. A `Registry` collects
+//! them by listener address and renders them, and `serve()` makes the result
+//! available over HTTP at `/metrics`.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use tracing::{debug, warn};
+
+/// A counter or a gauge.
+#[derive(Debug, Default)]
+pub struct Metric(AtomicU64);
+
+impl Metric {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, n: u64) {
+        self.0.store(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The metrics of one `EchoServer`.
+///
+/// The `quic_*` counters add up `quiche::Connection::stats()` of the
+/// connections that were collected.
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    pub connections_active: Metric,
+    pub connections_half_open: Metric,
+    pub connections_accepted: Metric,
+    pub connections_closed: Metric,
+    pub retry_sent: Metric,
+    pub version_negotiation_sent: Metric,
+    pub packets_discarded: Metric,
+    pub datagrams_received: Metric,
+    pub datagrams_sent: Metric,
+    pub bytes_received: Metric,
+    pub bytes_sent: Metric,
+    pub send_queue_depth: Metric,
+    pub send_queue_dropped: Metric,
+    pub quic_packets_received: Metric,
+    pub quic_packets_sent: Metric,
+    pub quic_packets_lost: Metric,
+    pub quic_packets_retransmitted: Metric,
+    pub dgram_received: Metric,
+    pub dgram_echoed: Metric,
+    pub dgram_dropped_queue_full: Metric,
+    pub dgram_dropped_oversize: Metric,
+}
+
+enum Kind {
+    Counter,
+    Gauge,
+}
+
+/// Name, type and help text of every metric, in the order of
+/// `ServerMetrics::values()`.
+const DESCRIPTIONS: [(&str, Kind, &str); 21] = [
+    (
+        "connections_active",
+        Kind::Gauge,
+        "Connections currently tracked.",
+    ),
+    (
+        "connections_half_open",
+        Kind::Gauge,
+        "Connections that have not completed the handshake.",
+    ),
+    (
+        "connections_accepted_total",
+        Kind::Counter,
+        "Connections accepted.",
+    ),
+    (
+        "connections_closed_total",
+        Kind::Counter,
+        "Connections closed and collected.",
+    ),
+    ("retry_sent_total", Kind::Counter, "Retry packets sent."),
+    (
+        "version_negotiation_sent_total",
+        Kind::Counter,
+        "Version negotiation packets sent.",
+    ),
+    (
+        "packets_discarded_total",
+        Kind::Counter,
+        "Received packets dropped without being processed by a connection.",
+    ),
+    (
+        "datagrams_received_total",
+        Kind::Counter,
+        "UDP datagrams received.",
+    ),
+    (
+        "datagrams_sent_total",
+        Kind::Counter,
+        "UDP datagrams sent or queued for sending.",
+    ),
+    (
+        "bytes_received_total",
+        Kind::Counter,
+        "UDP payload bytes received.",
+    ),
+    (
+        "bytes_sent_total",
+        Kind::Counter,
+        "UDP payload bytes sent or queued for sending.",
+    ),
+    (
+        "send_queue_depth",
+        Kind::Gauge,
+        "Datagrams waiting for the transport.",
+    ),
+    (
+        "send_queue_dropped_total",
+        Kind::Counter,
+        "Datagrams dropped because the send queue was full.",
+    ),
+    (
+        "quic_packets_received_total",
+        Kind::Counter,
+        "QUIC packets received by collected connections.",
+    ),
+    (
+        "quic_packets_sent_total",
+        Kind::Counter,
+        "QUIC packets sent by collected connections.",
+    ),
+    (
+        "quic_packets_lost_total",
+        Kind::Counter,
+        "QUIC packets lost by collected connections.",
+    ),
+    (
+        "quic_packets_retransmitted_total",
+        Kind::Counter,
+        "QUIC packets retransmitted by collected connections.",
+    ),
+    (
+        "dgram_received_total",
+        Kind::Counter,
+        "QUIC DATAGRAM frames received.",
+    ),
+    (
+        "dgram_echoed_total",
+        Kind::Counter,
+        "QUIC DATAGRAM frames queued as echoes.",
+    ),
+    (
+        "dgram_dropped_queue_full_total",
+        Kind::Counter,
+        "Datagram echoes dropped because the send queue was full.",
+    ),
+    (
+        "dgram_dropped_oversize_total",
+        Kind::Counter,
+        "Datagram echoes dropped because they exceed what the peer accepts.",
+    ),
+];
+
+impl ServerMetrics {
+    fn values(&self) -> [&Metric; 21] {
+        [
+            &self.connections_active,
+            &self.connections_half_open,
+            &self.connections_accepted,
+            &self.connections_closed,
+            &self.retry_sent,
+            &self.version_negotiation_sent,
+            &self.packets_discarded,
+            &self.datagrams_received,
+            &self.datagrams_sent,
+            &self.bytes_received,
+            &self.bytes_sent,
+            &self.send_queue_depth,
+            &self.send_queue_dropped,
+            &self.quic_packets_received,
+            &self.quic_packets_sent,
+            &self.quic_packets_lost,
+            &self.quic_packets_retransmitted,
+            &self.dgram_received,
+            &self.dgram_echoed,
+            &self.dgram_dropped_queue_full,
+            &self.dgram_dropped_oversize,
+        ]
+    }
+}
+
+/// Prefix of every metric name.
+const NAMESPACE: &str = "quic_echo";
+
+/// The metrics of all servers, labelled by listener address.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    servers: Arc<Mutex<Vec<(SocketAddr, Arc<ServerMetrics>)>>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    pub fn register(&self, listener: SocketAddr, metrics: Arc<ServerMetrics>) {
+        self.servers.lock().unwrap().push((listener, metrics));
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let servers = self.servers.lock().unwrap();
+
+        let mut out = String::new();
+        let values: Vec<_> = servers.iter().map(|(l, m)| (l, m.values())).collect();
+        for (i, (name, kind, help)) in DESCRIPTIONS.iter().enumerate() {
+            let kind = match kind {
+                Kind::Counter => "counter",
+                Kind::Gauge => "gauge",
+            };
+            writeln!(out, "# HELP {}_{} {}", NAMESPACE, name, help).unwrap();
+            writeln!(out, "# TYPE {}_{} {}", NAMESPACE, name, kind).unwrap();
+
+            for (listener, metrics) in &values {
+                writeln!(
+                    out,
+                    "{}_{}{{listener=\"{}\"}} {}",
+                    NAMESPACE,
+                    name,
+                    listener,
+                    metrics[i].get()
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Serves `registry` at `http://addr/metrics` from a background thread.
+///
+/// Returns the address actually bound, which differs from `addr` if its port
+/// is 0.
+pub fn serve(addr: SocketAddr, registry: Registry) -> io::Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    let handle = std::thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|s| handle_request(s, &registry));
+                if let Err(e) = result {
+                    warn!(error = ?e, "metrics request failed");
+                }
+            }
+        })?;
+
+    Ok((local_addr, handle))
+}
+
+fn handle_request(stream: TcpStream, registry: &Registry) -> io::Result<()> {
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Skip the headers, nothing in them matters.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    debug!(request = request_line.trim_end(), "metrics request");
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", registry.render()),
+
+        (Some("GET"), Some(_)) => ("404 Not Found", "not found\n".to_string()),
+
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
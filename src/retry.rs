@@ -0,0 +1,114 @@
+//! Deciding when a new connection has to prove its address with a Retry.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::config::ServerConfig;
+
+/// When the server answers an Initial packet without a token with a Retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RetryPolicy {
+    /// Every new connection is retried.
+    Always,
+    /// No connection is retried.
+    Never,
+    /// Connections are retried only while the number of half-open
+    /// connections, or the rate of Initial packets from the client's IP
+    /// address, is above its threshold.
+    UnderLoad,
+}
+
+impl FromStr for RetryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RetryPolicy, String> {
+        match s {
+            "always" => Ok(RetryPolicy::Always),
+            "never" => Ok(RetryPolicy::Never),
+            "under-load" => Ok(RetryPolicy::UnderLoad),
+            _ => Err(format!(
+                "unknown retry policy {:?}, expected always, never or under-load",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            RetryPolicy::Always => "always",
+            RetryPolicy::Never => "never",
+            RetryPolicy::UnderLoad => "under-load",
+        };
+        f.write_str(s)
+    }
+}
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Number of tracked sources above which stale windows are pruned.
+const MAX_IDLE_SOURCES: usize = 1024;
+
+/// Applies a `RetryPolicy`, counting Initial packets per source IP address in
+/// one-second windows.
+pub(crate) struct AddressValidation {
+    policy: RetryPolicy,
+    max_half_open: usize,
+    max_initial_rate: u32,
+    sources: HashMap<IpAddr, (Instant, u32)>,
+    pruned_at: Instant,
+}
+
+impl AddressValidation {
+    pub fn new(config: &ServerConfig) -> AddressValidation {
+        AddressValidation {
+            policy: config.retry_policy,
+            max_half_open: config.retry_max_half_open,
+            max_initial_rate: config.retry_max_initial_rate,
+            sources: HashMap::new(),
+            pruned_at: Instant::now(),
+        }
+    }
+
+    /// Records an Initial packet without a token from `src` and tells whether
+    /// it has to be answered with a Retry.
+    pub fn should_retry(&mut self, src: IpAddr, half_open: usize, now: Instant) -> bool {
+        match self.policy {
+            RetryPolicy::Always => true,
+
+            RetryPolicy::Never => false,
+
+            RetryPolicy::UnderLoad => {
+                let rate = self.record(src, now);
+                half_open >= self.max_half_open || rate > self.max_initial_rate
+            }
+        }
+    }
+
+    /// Counts an Initial packet from `src`, returning the number of packets
+    /// seen from it in the current window.
+    fn record(&mut self, src: IpAddr, now: Instant) -> u32 {
+        if self.sources.len() > MAX_IDLE_SOURCES
+            && now.saturating_duration_since(self.pruned_at) >= RATE_WINDOW
+        {
+            self.sources
+                .retain(|_, (start, _)| now.saturating_duration_since(*start) < RATE_WINDOW);
+            self.pruned_at = now;
+        }
+
+        let (start, count) = self.sources.entry(src).or_insert((now, 0));
+        if now.saturating_duration_since(*start) >= RATE_WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count
+    }
+}
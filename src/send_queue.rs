@@ -0,0 +1,114 @@
+//! Outbound datagrams waiting for the transport to accept them.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+
+use crate::transport::DatagramTransport;
+
+/// Counters describing a `SendQueue`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SendQueueStats {
+    /// Datagrams currently queued.
+    pub queued: usize,
+    /// Largest number of datagrams that were queued at the same time.
+    pub peak: usize,
+    /// Datagrams dropped because the queue was full.
+    pub dropped: u64,
+}
+
+/// A bounded FIFO of datagrams that could not be sent right away.
+///
+/// Datagrams are handed to the transport directly while nothing is queued,
+/// and queued once the transport reports `WouldBlock`, so they always leave in
+/// the order they were generated.
+pub struct SendQueue {
+    packets: VecDeque<(Vec<u8>, SocketAddr)>,
+    capacity: usize,
+    peak: usize,
+    dropped: u64,
+}
+
+impl SendQueue {
+    pub fn new(capacity: usize) -> SendQueue {
+        SendQueue {
+            packets: VecDeque::new(),
+            capacity,
+            peak: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Sends `buf` to `to`, or queues it if the transport is busy, and
+    /// returns whether it did either.
+    ///
+    /// The datagram is dropped, and counted as such, if the queue is full.
+    /// Only errors other than `WouldBlock` are returned.
+    pub fn send<T: DatagramTransport>(
+        &mut self,
+        transport: &mut T,
+        buf: &[u8],
+        to: SocketAddr,
+    ) -> io::Result<bool> {
+        if self.packets.is_empty() {
+            match transport.send_to(buf, to) {
+                Ok(_) => return Ok(true),
+
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(self.push(buf, to))
+    }
+
+    /// Hands queued datagrams to the transport until the queue is empty or
+    /// the transport returns an error, `WouldBlock` included.
+    ///
+    /// A datagram failing with any other error is removed from the queue, so
+    /// that a single unreachable peer does not hold up the others.
+    pub fn flush<T: DatagramTransport>(&mut self, transport: &mut T) -> io::Result<()> {
+        while let Some((buf, to)) = self.packets.front() {
+            match transport.send_to(buf, *to) {
+                Ok(_) => (),
+
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(e),
+
+                Err(e) => {
+                    self.packets.pop_front();
+                    return Err(e);
+                }
+            }
+            self.packets.pop_front();
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, buf: &[u8], to: SocketAddr) -> bool {
+        if self.is_full() {
+            self.dropped += 1;
+            return false;
+        }
+
+        self.packets.push_back((buf.to_vec(), to));
+        self.peak = std::cmp::max(self.peak, self.packets.len());
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.packets.len() >= self.capacity
+    }
+
+    pub fn stats(&self) -> SendQueueStats {
+        SendQueueStats {
+            queued: self.packets.len(),
+            peak: self.peak,
+            dropped: self.dropped,
+        }
+    }
+}
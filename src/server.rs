@@ -0,0 +1,790 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, info_span, trace, warn};
+
+use crate::app::{self, Application, ApplicationFactory, Routes};
+use crate::close_log::{CloseRecord, CloseSink, JsonLines};
+use crate::config::ServerConfig;
+use crate::metrics::ServerMetrics;
+use crate::retry::AddressValidation;
+use crate::send_queue::{SendQueue, SendQueueStats};
+use crate::timer::TimerWheel;
+use crate::token::RetryTokens;
+use crate::transport::DatagramTransport;
+use crate::{EchoServerError, EchoServerResult};
+
+struct Client {
+    conn: std::pin::Pin<Box<quiche::Connection>>,
+    /// Carries the trace ID, listener and peer of the connection.
+    span: tracing::Span,
+    peer: SocketAddr,
+    accepted: Instant,
+    established: bool,
+    /// Chosen by ALPN once the handshake, or 0-RTT, got far enough.
+    app: Option<Box<dyn Application>>,
+}
+
+type ClientMap = HashMap<quiche::ConnectionId<'static>, Client>;
+
+/// Resolution and span of the connection timer wheel.
+const TIMER_TICK: Duration = Duration::from_millis(1);
+const TIMER_SLOTS: usize = 1024;
+
+/// Re-arms the timer of connection `id` after quiche may have changed its
+/// timeout.
+fn arm_timer(
+    timers: &mut TimerWheel<quiche::ConnectionId<'static>>,
+    id: &quiche::ConnectionId<'static>,
+    conn: &quiche::Connection,
+) {
+    match conn.timeout() {
+        Some(timeout) => timers.set(id.clone(), Instant::now() + timeout),
+
+        None => timers.cancel(id),
+    }
+}
+
+/// A QUIC server echoing stream data back to its clients, reading and writing
+/// packets through a `DatagramTransport`.
+pub struct EchoServer<T> {
+    transport: T,
+    local_addr: SocketAddr,
+    /// Carries the listener address of events outside of a connection.
+    span: tracing::Span,
+    buf: [u8; 65535],
+    dgram_buf: [u8; 65535],
+    out: Vec<u8>,
+    from: SocketAddr,
+    recv_len: usize,
+    send_len: usize,
+    clients: ClientMap,
+    timers: TimerWheel<quiche::ConnectionId<'static>>,
+    quic_config: quiche::Config,
+    keylog: Option<std::fs::File>,
+    qlog_dir: Option<PathBuf>,
+    conn_id_seed: ring::hmac::Key,
+    retry_tokens: RetryTokens,
+    address_validation: AddressValidation,
+    half_open: usize,
+    send_queue: SendQueue,
+    metrics: Arc<ServerMetrics>,
+    close_sink: Option<Box<dyn CloseSink + Send>>,
+    shutdown_error_code: u64,
+    /// No connection is accepted any more.
+    shutting_down: bool,
+    apps: Routes,
+}
+
+impl<T: DatagramTransport> EchoServer<T> {
+    pub fn recv_quic_packets(&mut self) -> EchoServerResult<()> {
+        let _span = self.span.clone().entered();
+        loop {
+            let (read, from) = match self.transport.recv_from(&mut self.buf) {
+                Ok(v) => v,
+
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    break;
+                }
+
+                // An ICMP error for an earlier datagram only concerns that
+                // peer.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => {
+                    debug!(error = ?e, "recv_from() failed");
+                    continue;
+                }
+
+                Err(e) => {
+                    return Err(EchoServerError::Io("recv_from()".to_string(), e));
+                }
+            };
+            self.recv_len = read;
+            self.from = from;
+            trace!(peer = %from, bytes = read, "recv_from()");
+            self.metrics.datagrams_received.inc();
+            self.metrics.bytes_received.add(read as u64);
+            match self.process_quic_packets() {
+                Err(EchoServerError::Discarded) => self.metrics.packets_discarded.inc(),
+
+                Err(e) if e.is_fatal() => return Err(e),
+
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn process_quic_packets(&mut self) -> EchoServerResult<()> {
+        let pkt_buf = &mut self.buf[..self.recv_len];
+        let hdr = match quiche::Header::from_slice(pkt_buf, quiche::MAX_CONN_ID_LEN) {
+            Ok(v) => v,
+
+            Err(e) => {
+                debug!(peer = %self.from, error = ?e, "parsing packet header failed");
+                return Err(EchoServerError::Discarded);
+            }
+        };
+
+        let conn_id = ring::hmac::sign(&self.conn_id_seed, &hdr.dcid);
+        let conn_id = &conn_id.as_ref()[..quiche::MAX_CONN_ID_LEN];
+        let conn_id = conn_id.to_vec().into();
+        let new_conn =
+            !self.clients.contains_key(&hdr.dcid) && !self.clients.contains_key(&conn_id);
+        if new_conn {
+            match self.handle_handshake(&hdr, &conn_id) {
+                Ok(Some((scid, client))) => {
+                    self.clients.insert(scid, client);
+                    self.half_open += 1;
+                    self.metrics.connections_accepted.inc();
+                    self.update_connection_gauges();
+                }
+                Ok(None) => {
+                    match self.send_queue.send(
+                        &mut self.transport,
+                        &self.out[..self.send_len],
+                        self.from,
+                    ) {
+                        Ok(true) => self.count_sent(self.send_len),
+
+                        Ok(false) => debug!(peer = %self.from, "send queue is full, reply dropped"),
+
+                        Err(e) => warn!(peer = %self.from, error = ?e, "send_to() failed"),
+                    }
+                    return Ok(());
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+
+        self.handle_after_established(&hdr.dcid, &conn_id);
+
+        Ok(())
+    }
+    fn handle_handshake(
+        &mut self,
+        hdr: &quiche::Header,
+        conn_id: &quiche::ConnectionId,
+    ) -> EchoServerResult<Option<(quiche::ConnectionId<'static>, Client)>> {
+        if hdr.ty != quiche::Type::Initial {
+            debug!(peer = %self.from, "packet is not Initial");
+            return Err(EchoServerError::Discarded);
+        }
+        if self.shutting_down {
+            debug!(peer = %self.from, "shutting down, not accepting connections");
+            return Err(EchoServerError::Discarded);
+        }
+        if !quiche::version_is_supported(hdr.version) {
+            debug!(peer = %self.from, version = hdr.version, "doing version negotiation");
+
+            self.send_len = match quiche::negotiate_version(&hdr.scid, &hdr.dcid, &mut self.out) {
+                Ok(v) => v,
+
+                Err(e) => {
+                    warn!(peer = %self.from, error = ?e, "negotiate_version() failed");
+                    return Err(EchoServerError::Discarded);
+                }
+            };
+            self.metrics.version_negotiation_sent.inc();
+            return Ok(None);
+        }
+
+        let mut scid = [0; quiche::MAX_CONN_ID_LEN];
+        scid.copy_from_slice(&conn_id);
+
+        let scid = quiche::ConnectionId::from_ref(&scid);
+
+        if self.retry_tokens.rotate_if_due(Instant::now()).is_err() {
+            warn!("retry token key rotation failed");
+        }
+
+        // Token is always present in Initial packets.
+        let token = match hdr.token.as_ref() {
+            Some(v) => v,
+
+            None => return Err(EchoServerError::Discarded),
+        };
+
+        let (scid, odcid) = if token.is_empty() {
+            // Do stateless retry if the client didn't send a token and the
+            // policy asks for it.
+            if self
+                .address_validation
+                .should_retry(self.from.ip(), self.half_open, Instant::now())
+            {
+                debug!(peer = %self.from, "doing stateless retry");
+
+                // Without a token the client cannot be validated, so its
+                // Initial is dropped rather than accepted unvalidated.
+                let new_token = match self.retry_tokens.mint(&hdr.dcid, &self.from) {
+                    Ok(v) => v,
+
+                    Err(_) => {
+                        warn!(peer = %self.from, "minting the retry token failed");
+                        return Err(EchoServerError::Discarded);
+                    }
+                };
+
+                self.send_len = match quiche::retry(
+                    &hdr.scid,
+                    &hdr.dcid,
+                    &scid,
+                    &new_token,
+                    hdr.version,
+                    &mut self.out,
+                ) {
+                    Ok(v) => v,
+
+                    Err(e) => {
+                        warn!(peer = %self.from, error = ?e, "retry() failed");
+                        return Err(EchoServerError::Discarded);
+                    }
+                };
+                self.metrics.retry_sent.inc();
+
+                return Ok(None);
+            }
+
+            (quiche::ConnectionId::from_vec(scid.to_vec()), None)
+        } else {
+            let odcid = self.retry_tokens.validate(&self.from, token);
+
+            // The token was not valid, meaning the retry failed, so
+            // drop the packet.
+            if odcid.is_none() {
+                debug!(peer = %self.from, "invalid address validation token");
+                return Err(EchoServerError::Discarded);
+            }
+
+            if scid.len() != hdr.dcid.len() {
+                debug!(peer = %self.from, "invalid destination connection ID");
+                return Err(EchoServerError::Discarded);
+            }
+
+            // Reuse the source connection ID we sent in the Retry packet,
+            // instead of changing it again.
+            (quiche::ConnectionId::from_vec(hdr.dcid.to_vec()), odcid)
+        };
+
+        let mut conn = match quiche::accept(&scid, odcid.as_ref(), self.from, &mut self.quic_config)
+        {
+            Ok(v) => v,
+
+            Err(e) => {
+                warn!(peer = %self.from, error = ?e, "accept() failed");
+                return Err(EchoServerError::Discarded);
+            }
+        };
+
+        if let Some(keylog) = &mut self.keylog {
+            if let Ok(keylog) = keylog.try_clone() {
+                conn.set_keylog(Box::new(keylog));
+            }
+        }
+
+        if let Some(dir) = &self.qlog_dir {
+            let trace_id = conn.trace_id().to_string();
+            let path = dir.join(format!("{}.sqlog", trace_id));
+            match std::fs::File::create(&path) {
+                Ok(file) => conn.set_qlog(
+                    Box::new(std::io::BufWriter::new(file)),
+                    "quic_echo qlog".to_string(),
+                    format!("quic_echo server qlog id={}", trace_id),
+                ),
+
+                Err(e) => warn!(path = %path.display(), error = ?e, "creating qlog file failed"),
+            }
+        }
+
+        // Connection spans stand alone, so that their events do not repeat
+        // the listener span.
+        let span = info_span!(
+            parent: None,
+            "conn",
+            trace_id = %conn.trace_id(),
+            listener = %self.local_addr,
+            peer = %self.from,
+        );
+        span.in_scope(|| info!(dcid = ?hdr.dcid, scid = ?scid, "new connection"));
+
+        Ok(Some((
+            scid,
+            Client {
+                conn,
+                span,
+                peer: self.from,
+                accepted: Instant::now(),
+                established: false,
+                app: None,
+            },
+        )))
+    }
+
+    fn handle_after_established(
+        &mut self,
+        dcid: &quiche::ConnectionId<'static>,
+        conn_id: &quiche::ConnectionId<'static>,
+    ) {
+        let pkt_buf = &mut self.buf[..self.recv_len];
+        let recv_info = quiche::RecvInfo { from: self.from };
+        let id = if self.clients.contains_key(dcid) {
+            dcid
+        } else {
+            conn_id
+        };
+        let client = self.clients.get_mut(id).unwrap();
+        let _span = client.span.clone().entered();
+
+        // Process potentially coalesced packets.
+        let recv = client.conn.recv(pkt_buf, recv_info);
+        arm_timer(&mut self.timers, id, &client.conn);
+        let read = match recv {
+            Ok(v) => v,
+
+            Err(e) => {
+                debug!(error = ?e, "recv failed");
+                return;
+            }
+        };
+
+        trace!(bytes = read, "processed");
+
+        let just_established = !client.established && client.conn.is_established();
+        if just_established {
+            client.established = true;
+            self.half_open -= 1;
+            self.metrics
+                .connections_half_open
+                .set(self.half_open as u64);
+        }
+
+        if !client.conn.is_in_early_data() && !client.conn.is_established() {
+            return;
+        }
+
+        if client.app.is_none() {
+            let alpn = client.conn.application_proto().to_vec();
+            client.app = self.apps.create(&alpn);
+            if client.app.is_none() {
+                warn!(alpn = %String::from_utf8_lossy(&alpn), "no application for ALPN");
+                client
+                    .conn
+                    .close(
+                        false,
+                        app::NO_APPLICATION_PROTOCOL,
+                        b"no application for ALPN",
+                    )
+                    .ok();
+                return;
+            }
+            debug!(alpn = %String::from_utf8_lossy(&alpn), "application selected");
+        }
+        let app = match client.app.as_mut() {
+            Some(v) => v,
+
+            // Closing for lack of an application.
+            None => return,
+        };
+
+        if just_established {
+            app.on_established(&mut client.conn);
+        }
+
+        // Let the application write what flow control held back before it
+        // reads more.
+        for s in client.conn.writable() {
+            app.on_stream_writable(&mut client.conn, s);
+        }
+
+        for s in client.conn.readable() {
+            app.on_stream_readable(&mut client.conn, s);
+        }
+
+        while let Ok(len) = client.conn.dgram_recv(&mut self.dgram_buf) {
+            trace!(bytes = len, "datagram received");
+            self.metrics.dgram_received.inc();
+            app.on_datagram(&mut client.conn, &self.dgram_buf[..len]);
+        }
+    }
+
+    pub fn send_quic_packets(&mut self) -> EchoServerResult<()> {
+        let result = self.send_pending_packets();
+
+        let stats = self.send_queue.stats();
+        self.metrics.send_queue_depth.set(stats.queued as u64);
+        self.metrics.send_queue_dropped.set(stats.dropped);
+
+        result
+    }
+
+    fn send_pending_packets(&mut self) -> EchoServerResult<()> {
+        let _span = self.span.clone().entered();
+
+        // Send what the transport could not take last time first, so packets
+        // leave in the order they were generated.
+        loop {
+            match self.send_queue.flush(&mut self.transport) {
+                Ok(()) => break,
+
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Err(EchoServerError::SendPending);
+                }
+
+                // The datagram is dropped, QUIC recovers from that like from
+                // any other loss.
+                Err(e) => {
+                    warn!(error = ?e, "send_to() failed");
+                }
+            }
+        }
+
+        // Generate outgoing QUIC packets for all active connections and send
+        // them on the UDP socket, until quiche reports that there are no more
+        // packets to be sent. Packets are only generated while there is room
+        // to queue them, quiche keeps the rest until the next call.
+        let mut queue_full = false;
+        for (id, client) in self.clients.iter_mut() {
+            let _span = client.span.clone().entered();
+            let mut changed = false;
+            while !queue_full {
+                if self.send_queue.is_full() {
+                    debug!("send queue is full");
+                    queue_full = true;
+                    break;
+                }
+
+                let (write, send_info) = match client.conn.send(&mut self.out) {
+                    Ok(v) => v,
+
+                    Err(quiche::Error::Done) => {
+                        trace!("done writing");
+                        break;
+                    }
+
+                    // Only this connection is closed, the others are still
+                    // served.
+                    Err(e) => {
+                        warn!(error = ?e, "send failed");
+
+                        client.conn.close(false, 0x1, b"fail").ok();
+                        changed = true;
+                        break;
+                    }
+                };
+                changed = true;
+
+                match self
+                    .send_queue
+                    .send(&mut self.transport, &self.out[..write], send_info.to)
+                {
+                    Ok(true) => {
+                        self.metrics.datagrams_sent.inc();
+                        self.metrics.bytes_sent.add(write as u64);
+                    }
+
+                    // Packets are only generated while the queue has room.
+                    Ok(false) => (),
+
+                    Err(e) => warn!(to = %send_info.to, error = ?e, "send_to() failed"),
+                }
+                trace!(bytes = write, "written");
+            }
+
+            // Connections that sent nothing keep the timer armed when they
+            // last received a packet or timed out.
+            if changed {
+                arm_timer(&mut self.timers, id, &client.conn);
+            }
+        }
+
+        if !self.send_queue.is_empty() {
+            return Err(EchoServerError::SendPending);
+        }
+        Ok(())
+    }
+
+    /// Returns how long until `on_timeout()` has connections to handle.
+    pub fn timeout(&mut self) -> Option<Duration> {
+        self.timers
+            .next_expiry()
+            .map(|t| t.saturating_duration_since(Instant::now()))
+    }
+
+    /// Lets quiche handle the timers of the connections whose timeout has
+    /// expired.
+    pub fn on_timeout(&mut self) {
+        for id in self.timers.expire(Instant::now()) {
+            if let Some(client) = self.clients.get_mut(&id) {
+                client.span.in_scope(|| trace!("timeout"));
+                client.conn.on_timeout();
+                arm_timer(&mut self.timers, &id, &client.conn);
+            }
+        }
+    }
+
+    /// Stops accepting connections and closes the existing ones with the
+    /// configured `shutdown_error_code`.
+    ///
+    /// The connections are collected by `remove_closed_connections()` once
+    /// their closing period is over, after which `num_clients()` is 0.
+    pub fn shutdown(&mut self) {
+        let _span = self.span.clone().entered();
+        info!(connections = self.clients.len(), "shutting down");
+
+        self.shutting_down = true;
+        for client in self.clients.values_mut() {
+            // Connections that are already closing return Done.
+            client
+                .conn
+                .close(true, self.shutdown_error_code, b"server shutdown")
+                .ok();
+        }
+    }
+
+    pub fn remove_closed_connections(&mut self) -> EchoServerResult<()> {
+        let metrics = &self.metrics;
+        self.clients.retain(|id, ref mut c| {
+            if c.conn.is_closed() {
+                self.timers.cancel(id);
+                if !c.established {
+                    self.half_open -= 1;
+                }
+
+                if let Some(app) = c.app.as_mut() {
+                    c.span.in_scope(|| app.on_closed(&c.conn));
+                }
+
+                let stats = c.conn.stats();
+                metrics.connections_closed.inc();
+                metrics.quic_packets_received.add(stats.recv as u64);
+                metrics.quic_packets_sent.add(stats.sent as u64);
+                metrics.quic_packets_lost.add(stats.lost as u64);
+                metrics.quic_packets_retransmitted.add(stats.retrans as u64);
+                c.span
+                    .in_scope(|| info!(stats = ?stats, "connection collected"));
+
+                if let Some(sink) = &mut self.close_sink {
+                    let record = CloseRecord::new(
+                        &c.conn,
+                        self.local_addr,
+                        c.peer,
+                        c.established,
+                        c.accepted.elapsed(),
+                    );
+                    if let Err(e) = sink.record(&record) {
+                        c.span
+                            .in_scope(|| warn!(error = ?e, "recording the close failed"));
+                    }
+                }
+            }
+
+            !c.conn.is_closed()
+        });
+        self.update_connection_gauges();
+        Ok(())
+    }
+
+    fn update_connection_gauges(&self) {
+        self.metrics
+            .connections_active
+            .set(self.clients.len() as u64);
+        self.metrics
+            .connections_half_open
+            .set(self.half_open as u64);
+    }
+
+    /// Counts a datagram the send queue sent or kept.
+    fn count_sent(&self, len: usize) {
+        self.metrics.datagrams_sent.inc();
+        self.metrics.bytes_sent.add(len as u64);
+    }
+
+    pub fn new(transport: T, config: &ServerConfig) -> EchoServerResult<EchoServer<T>> {
+        let mut quic_config = config.quiche_config()?;
+
+        let mut keylog = None;
+        if let Some(keylog_path) = std::env::var_os("SSLKEYLOGFILE") {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&keylog_path)
+                .map_err(|e| {
+                    EchoServerError::Io(
+                        format!("opening SSLKEYLOGFILE {}", keylog_path.to_string_lossy()),
+                        e,
+                    )
+                })?;
+
+            keylog = Some(file);
+
+            quic_config.log_keys();
+        }
+        if let Some(dir) = &config.qlog_dir {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                EchoServerError::Io(format!("creating qlog directory {}", dir.display()), e)
+            })?;
+        }
+
+        let close_sink = match &config.close_log {
+            Some(path) => {
+                let sink = JsonLines::append(path).map_err(|e| {
+                    EchoServerError::Io(format!("opening close log {}", path.display()), e)
+                })?;
+                Some(Box::new(sink) as Box<dyn CloseSink + Send>)
+            }
+
+            None => None,
+        };
+
+        let rng = ring::rand::SystemRandom::new();
+        let conn_id_seed =
+            ring::hmac::Key::generate(ring::hmac::HMAC_SHA256, &rng).map_err(|_| {
+                EchoServerError::Crypto("generating the connection ID seed".to_string())
+            })?;
+        let retry_tokens =
+            RetryTokens::new(config.retry_token_lifetime, config.retry_token_rotation).map_err(
+                |_| EchoServerError::Crypto("generating the retry token key".to_string()),
+            )?;
+
+        let local_addr = transport
+            .local_addr()
+            .map_err(|e| EchoServerError::Io("local_addr()".to_string(), e))?;
+
+        let metrics = Arc::default();
+        let apps = Routes::for_config(config, &metrics);
+
+        Ok(EchoServer {
+            transport,
+            local_addr,
+            span: info_span!("listener", addr = %local_addr),
+            buf: [0; 65535],
+            dgram_buf: [0; 65535],
+            out: vec![0; config.max_udp_payload_size],
+            from: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            recv_len: 0,
+            send_len: 0,
+            clients: ClientMap::new(),
+            timers: TimerWheel::new(TIMER_TICK, TIMER_SLOTS, Instant::now()),
+            quic_config,
+            keylog,
+            qlog_dir: config.qlog_dir.clone(),
+            conn_id_seed,
+            retry_tokens,
+            address_validation: AddressValidation::new(config),
+            half_open: 0,
+            send_queue: SendQueue::new(config.send_queue_capacity),
+            metrics,
+            close_sink,
+            shutdown_error_code: config.shutdown_error_code,
+            shutting_down: false,
+            apps,
+        })
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Returns the number of connections currently tracked by the server.
+    pub fn num_clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Returns the depth and drop counters of the outbound packet queue.
+    pub fn send_queue_stats(&self) -> SendQueueStats {
+        self.send_queue.stats()
+    }
+
+    /// Sends a `CloseRecord` to `sink` for every connection collected from
+    /// now on, instead of to the `close_log` file of the configuration.
+    pub fn set_close_sink(&mut self, sink: Box<dyn CloseSink + Send>) {
+        self.close_sink = Some(sink);
+    }
+
+    /// Returns the counters of this server, to be registered with a
+    /// `metrics::Registry`.
+    pub fn metrics(&self) -> &Arc<ServerMetrics> {
+        &self.metrics
+    }
+}
+
+/// Builds an `EchoServer` on top of a `DatagramTransport`.
+#[derive(Default)]
+pub struct EchoServerBuilder {
+    config: ServerConfig,
+    apps: Routes,
+}
+
+impl EchoServerBuilder {
+    pub fn new() -> EchoServerBuilder {
+        EchoServerBuilder::default()
+    }
+
+    /// Replaces all settings with `config`.
+    pub fn config(mut self, config: ServerConfig) -> EchoServerBuilder {
+        self.config = config;
+        self
+    }
+
+    /// Sets the PEM file containing the certificate chain.
+    pub fn cert_chain(mut self, path: impl Into<PathBuf>) -> EchoServerBuilder {
+        self.config.cert_chain = path.into();
+        self
+    }
+
+    /// Sets the PEM file containing the private key.
+    pub fn priv_key(mut self, path: impl Into<PathBuf>) -> EchoServerBuilder {
+        self.config.priv_key = path.into();
+        self
+    }
+
+    /// Sets the application protocols offered during the handshake.
+    pub fn application_protos(mut self, protos: &[&str]) -> EchoServerBuilder {
+        self.config.application_protos = protos.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Sets the application serving connections whose ALPN has no
+    /// application of its own.
+    pub fn application(mut self, factory: ApplicationFactory) -> EchoServerBuilder {
+        self.apps.fallback(factory);
+        self
+    }
+
+    /// Sets the application serving connections that negotiated `alpn`.
+    ///
+    /// Once an application is set, connections negotiating an ALPN without
+    /// one are closed. Without any, the default routes described in `app`
+    /// apply.
+    pub fn alpn_application(
+        mut self,
+        alpn: &str,
+        factory: ApplicationFactory,
+    ) -> EchoServerBuilder {
+        self.apps.route(alpn, factory);
+        self
+    }
+
+    pub fn build<T: DatagramTransport>(self, transport: T) -> EchoServerResult<EchoServer<T>> {
+        let mut server = EchoServer::new(transport, &self.config)?;
+        if !self.apps.is_empty() {
+            for alpn in &self.config.application_protos {
+                if !self.apps.serves(alpn.as_bytes()) {
+                    warn!(alpn = %alpn, "ALPN offered without an application");
+                }
+            }
+            server.apps = self.apps;
+        }
+        Ok(server)
+    }
+}
@@ -0,0 +1,145 @@
+//! A hashed timer wheel for connection timeouts.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Timers keyed by `K`, each firing once at its deadline.
+///
+/// Time is divided into ticks, and a timer is stored in the slot of the first
+/// tick at or after its deadline, so timers fire at most one tick late and
+/// never early. Deadlines further away than a full turn of the wheel share a
+/// slot with nearer ones and are kept until their turn comes.
+///
+/// Re-arming a timer does not look for its previous entry: the entry is left
+/// in place and skipped once it is reached, as it no longer matches the
+/// deadline recorded for the key. Re-arming within the tick of the current
+/// deadline keeps that deadline, as both fire at the same expiry.
+pub struct TimerWheel<K> {
+    tick: Duration,
+    slots: Vec<Vec<(K, Instant)>>,
+    armed: HashMap<K, Instant>,
+    origin: Instant,
+    /// The first tick that has not been expired yet.
+    cursor: u64,
+}
+
+impl<K: Clone + Hash + Eq> TimerWheel<K> {
+    /// Creates a wheel of `slots` ticks of `tick` each, starting at `now`.
+    pub fn new(tick: Duration, slots: usize, now: Instant) -> TimerWheel<K> {
+        assert!(tick > Duration::ZERO && slots > 0);
+
+        TimerWheel {
+            tick,
+            slots: (0..slots).map(|_| Vec::new()).collect(),
+            armed: HashMap::new(),
+            origin: now,
+            cursor: 0,
+        }
+    }
+
+    /// Arms the timer of `key` for `deadline`, replacing its previous
+    /// deadline.
+    pub fn set(&mut self, key: K, deadline: Instant) {
+        let tick = self.slot_tick(deadline);
+        if let Some(armed) = self.armed.get(&key) {
+            if self.slot_tick(*armed) == tick {
+                return;
+            }
+        }
+
+        let slot = (tick % self.slots.len() as u64) as usize;
+        self.slots[slot].push((key.clone(), deadline));
+        self.armed.insert(key, deadline);
+    }
+
+    /// Disarms the timer of `key`.
+    pub fn cancel(&mut self, key: &K) {
+        self.armed.remove(key);
+    }
+
+    /// Returns the deadline of `key`, if its timer is armed.
+    pub fn deadline(&self, key: &K) -> Option<Instant> {
+        self.armed.get(key).copied()
+    }
+
+    /// Returns the number of armed timers.
+    pub fn len(&self) -> usize {
+        self.armed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.armed.is_empty()
+    }
+
+    /// Returns the number of entries in the slots, stale ones included.
+    pub fn entries(&self) -> usize {
+        self.slots.iter().map(Vec::len).sum()
+    }
+
+    /// Returns when `expire()` should be called next.
+    ///
+    /// This is the start of the next tick holding an entry, which may turn
+    /// out to be a stale or a later turn's entry, so it is never later than
+    /// the earliest deadline but may be earlier.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        if self.armed.is_empty() {
+            return None;
+        }
+
+        let n = self.slots.len() as u64;
+        (self.cursor..self.cursor + n)
+            .find(|tick| !self.slots[(tick % n) as usize].is_empty())
+            .map(|tick| self.tick_start(tick))
+    }
+
+    /// Disarms and returns the keys whose deadline is not after `now`.
+    pub fn expire(&mut self, now: Instant) -> Vec<K> {
+        let mut expired = Vec::new();
+
+        let last = self.tick_before(now);
+        if last < self.cursor {
+            return expired;
+        }
+
+        // Past a full turn, every slot has been visited once.
+        let n = self.slots.len() as u64;
+        let end = std::cmp::min(last, self.cursor + n - 1);
+        for tick in self.cursor..=end {
+            let armed = &mut self.armed;
+            self.slots[(tick % n) as usize].retain(|(key, deadline)| {
+                let live = armed.get(key) == Some(deadline);
+                if live && *deadline <= now {
+                    armed.remove(key);
+                    expired.push(key.clone());
+                    return false;
+                }
+                live
+            });
+        }
+        self.cursor = last + 1;
+
+        expired
+    }
+
+    /// The tick whose slot holds a timer for `deadline`. A deadline in the
+    /// past fires on the next expiry.
+    fn slot_tick(&self, deadline: Instant) -> u64 {
+        std::cmp::max(self.tick_at_or_after(deadline), self.cursor)
+    }
+
+    fn tick_start(&self, tick: u64) -> Instant {
+        self.origin + Duration::from_nanos(self.tick.as_nanos() as u64 * tick)
+    }
+
+    fn tick_before(&self, t: Instant) -> u64 {
+        let elapsed = t.saturating_duration_since(self.origin).as_nanos();
+        (elapsed / self.tick.as_nanos()) as u64
+    }
+
+    fn tick_at_or_after(&self, t: Instant) -> u64 {
+        let elapsed = t.saturating_duration_since(self.origin).as_nanos();
+        let tick = self.tick.as_nanos();
+        ((elapsed + tick - 1) / tick) as u64
+    }
+}
@@ -0,0 +1,229 @@
+//! Stateless retry tokens.
+//!
+//! A token is the original destination connection ID chosen by the client and
+//! the time the token was issued, sealed with AES-128-GCM. The client address
+//! is used as additional authenticated data, so a token is only accepted from
+//! the address it was issued to, and only within its lifetime:
+//!
+//! ```text
+//! nonce (12 bytes) || seal(issued (8 bytes, seconds since UNIX epoch) || odcid) || tag (16 bytes)
+//! ```
+//!
+//! The key is replaced periodically. Tokens are always minted with the current
+//! key, but the previous key is still accepted so that a client that received
+//! a Retry just before the rotation can complete its handshake.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// An AEAD key sealing and opening retry tokens.
+pub struct TokenKey {
+    key: LessSafeKey,
+}
+
+impl TokenKey {
+    /// Generates a random key.
+    pub fn generate(rng: &dyn SecureRandom) -> Result<TokenKey, ring::error::Unspecified> {
+        let mut key_bytes = [0; 16];
+        rng.fill(&mut key_bytes)?;
+        TokenKey::new(&key_bytes)
+    }
+
+    /// Creates a key from 16 bytes of secret material.
+    pub fn new(key_bytes: &[u8]) -> Result<TokenKey, ring::error::Unspecified> {
+        let key = UnboundKey::new(&aead::AES_128_GCM, key_bytes)?;
+        Ok(TokenKey {
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    fn seal(
+        &self,
+        rng: &dyn SecureRandom,
+        aad: &[u8],
+        mut plaintext: Vec<u8>,
+    ) -> Result<Vec<u8>, ring::error::Unspecified> {
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill(&mut nonce)?;
+
+        self.key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut plaintext,
+        )?;
+
+        let mut token = nonce.to_vec();
+        token.extend_from_slice(&plaintext);
+        Ok(token)
+    }
+
+    fn open(&self, aad: &[u8], token: &[u8]) -> Option<Vec<u8>> {
+        if token.len() < NONCE_LEN {
+            return None;
+        }
+
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&token[..NONCE_LEN]);
+
+        let mut in_out = token.to_vec();
+        let plaintext = self
+            .key
+            .open_within(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad),
+                &mut in_out,
+                NONCE_LEN..,
+            )
+            .ok()?;
+        Some(plaintext.to_vec())
+    }
+}
+
+/// Mints and validates the tokens carried by Retry packets.
+pub struct RetryTokens {
+    current: TokenKey,
+    previous: Option<TokenKey>,
+    lifetime: Duration,
+    rotation_interval: Duration,
+    rotated_at: Instant,
+    rng: SystemRandom,
+}
+
+impl RetryTokens {
+    /// Creates a token minter with a random key, which is replaced every
+    /// `rotation_interval`. Tokens older than `lifetime` are rejected.
+    ///
+    /// `rotation_interval` should not be shorter than `lifetime`, otherwise
+    /// tokens may be rejected before they expire.
+    pub fn new(
+        lifetime: Duration,
+        rotation_interval: Duration,
+    ) -> Result<RetryTokens, ring::error::Unspecified> {
+        let rng = SystemRandom::new();
+        let key = TokenKey::generate(&rng)?;
+
+        Ok(RetryTokens::with_key(key, lifetime, rotation_interval))
+    }
+
+    pub fn with_key(key: TokenKey, lifetime: Duration, rotation_interval: Duration) -> RetryTokens {
+        RetryTokens {
+            current: key,
+            previous: None,
+            lifetime,
+            rotation_interval,
+            rotated_at: Instant::now(),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Replaces the current key with a random one if the rotation interval
+    /// has elapsed since the last rotation.
+    pub fn rotate_if_due(&mut self, now: Instant) -> Result<(), ring::error::Unspecified> {
+        let elapsed = now.saturating_duration_since(self.rotated_at);
+        if elapsed < self.rotation_interval {
+            return Ok(());
+        }
+
+        let key = TokenKey::generate(&self.rng)?;
+        self.rotate_to(key);
+        self.rotated_at = now;
+
+        // Nothing minted with the old current key can still be valid if it was
+        // not used for a whole interval.
+        if elapsed >= self.rotation_interval * 2 {
+            self.previous = None;
+        }
+
+        Ok(())
+    }
+
+    /// Makes `key` the current key, keeping the current key as the previous
+    /// one.
+    pub fn rotate_to(&mut self, key: TokenKey) {
+        let previous = std::mem::replace(&mut self.current, key);
+        self.previous = Some(previous);
+    }
+
+    /// Generates a stateless retry token for the client at `src`, carrying the
+    /// original destination connection ID `odcid`.
+    ///
+    /// Fails only if the system random number generator does.
+    pub fn mint(
+        &self,
+        odcid: &[u8],
+        src: &SocketAddr,
+    ) -> Result<Vec<u8>, ring::error::Unspecified> {
+        self.mint_at(odcid, src, SystemTime::now())
+    }
+
+    pub fn mint_at(
+        &self,
+        odcid: &[u8],
+        src: &SocketAddr,
+        now: SystemTime,
+    ) -> Result<Vec<u8>, ring::error::Unspecified> {
+        let issued = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
+
+        let mut plaintext = issued.to_be_bytes().to_vec();
+        plaintext.extend_from_slice(odcid);
+
+        self.current.seal(&self.rng, &addr_to_aad(src), plaintext)
+    }
+
+    /// Validates a stateless retry token.
+    ///
+    /// Returns the original destination connection ID if the token was minted
+    /// by this server for `src` and has not expired.
+    pub fn validate(
+        &self,
+        src: &SocketAddr,
+        token: &[u8],
+    ) -> Option<quiche::ConnectionId<'static>> {
+        self.validate_at(src, token, SystemTime::now())
+    }
+
+    pub fn validate_at(
+        &self,
+        src: &SocketAddr,
+        token: &[u8],
+        now: SystemTime,
+    ) -> Option<quiche::ConnectionId<'static>> {
+        let aad = addr_to_aad(src);
+        let plaintext = match self.current.open(&aad, token) {
+            Some(v) => v,
+
+            None => self.previous.as_ref()?.open(&aad, token)?,
+        };
+        if plaintext.len() < 8 || plaintext.len() - 8 > quiche::MAX_CONN_ID_LEN {
+            return None;
+        }
+
+        let mut issued = [0; 8];
+        issued.copy_from_slice(&plaintext[..8]);
+        let issued = UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(issued));
+
+        // Tokens from the future were still minted by us, so only their age
+        // matters.
+        let age = now.duration_since(issued).unwrap_or(Duration::ZERO);
+        if age > self.lifetime {
+            return None;
+        }
+
+        Some(quiche::ConnectionId::from_vec(plaintext[8..].to_vec()))
+    }
+}
+
+fn addr_to_aad(addr: &SocketAddr) -> Vec<u8> {
+    let mut aad = match addr.ip() {
+        IpAddr::V4(a) => a.octets().to_vec(),
+        IpAddr::V6(a) => a.octets().to_vec(),
+    };
+    aad.extend_from_slice(&addr.port().to_be_bytes());
+    aad
+}
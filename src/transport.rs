@@ -0,0 +1,64 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+mod loopback;
+pub use loopback::LoopbackTransport;
+
+mod udp;
+pub use udp::UdpTransport;
+
+#[cfg(windows)]
+mod winsock;
+#[cfg(windows)]
+pub use winsock::{wsa_cleanup, wsa_startup, WinSockTransport};
+
+/// The transport driven by `event_loop::run()` on this platform.
+#[cfg(unix)]
+pub type NativeTransport = UdpTransport;
+#[cfg(windows)]
+pub type NativeTransport = WinSockTransport;
+
+/// A non-blocking datagram socket that `EchoServer` reads QUIC packets from
+/// and writes QUIC packets to.
+///
+/// Both methods return an error of kind `io::ErrorKind::WouldBlock` when the
+/// operation cannot complete right now. The caller is expected to retry once
+/// the backend signals readiness again.
+pub trait DatagramTransport {
+    /// Receives a single datagram into `buf`, returning its length and the
+    /// address of the peer that sent it.
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+
+    /// Sends a single datagram to `to`, returning the number of bytes accepted.
+    fn send_to(&mut self, buf: &[u8], to: SocketAddr) -> io::Result<usize>;
+
+    /// Returns the address the transport is bound to.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+/// Turns an IPv4-mapped IPv6 address, as reported by dual-stack sockets for
+/// IPv4 peers, into the IPv4 address it stands for. Other addresses are
+/// returned unchanged.
+///
+/// Transports apply this to the addresses they receive from, so that a peer
+/// has the same address whichever kind of socket it reached.
+pub fn normalize_addr(addr: SocketAddr) -> SocketAddr {
+    if let IpAddr::V6(ip) = addr.ip() {
+        let octets = ip.octets();
+        if octets[..10] == [0; 10] && octets[10..12] == [0xff, 0xff] {
+            let ip = [octets[12], octets[13], octets[14], octets[15]];
+            return SocketAddr::new(IpAddr::from(ip), addr.port());
+        }
+    }
+    addr
+}
+
+/// The inverse of `normalize_addr()`, for sending to an IPv4 peer through an
+/// IPv6 socket.
+fn ipv4_mapped(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+
+        IpAddr::V6(_) => addr,
+    }
+}
@@ -0,0 +1,74 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use super::{ipv4_mapped, normalize_addr, DatagramTransport};
+
+/// A `DatagramTransport` backed by a non-blocking `std::net::UdpSocket`.
+pub struct UdpTransport {
+    socket: UdpSocket,
+    ipv6: bool,
+}
+
+impl UdpTransport {
+    /// Binds to `addr`. An IPv6 socket only accepts IPv6 peers.
+    pub fn bind(addr: SocketAddr) -> io::Result<UdpTransport> {
+        UdpTransport::bind_socket(addr, false)
+    }
+
+    /// Binds to the IPv6 address `addr`, accepting IPv4 peers too.
+    pub fn bind_dual_stack(addr: SocketAddr) -> io::Result<UdpTransport> {
+        if addr.is_ipv4() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a dual-stack socket needs an IPv6 address",
+            ));
+        }
+        UdpTransport::bind_socket(addr, true)
+    }
+
+    fn bind_socket(addr: SocketAddr, dual_stack: bool) -> io::Result<UdpTransport> {
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+        // Set explicitly, as the default differs between platforms.
+        if addr.is_ipv6() {
+            socket.set_only_v6(!dual_stack)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.set_nonblocking(true)?;
+
+        Ok(UdpTransport {
+            socket: socket.into(),
+            ipv6: addr.is_ipv6(),
+        })
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+}
+
+impl DatagramTransport for UdpTransport {
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (read, from) = self.socket.recv_from(buf)?;
+        Ok((read, normalize_addr(from)))
+    }
+
+    fn send_to(&mut self, buf: &[u8], to: SocketAddr) -> io::Result<usize> {
+        if self.ipv6 {
+            return self.socket.send_to(buf, ipv4_mapped(to));
+        }
+        self.socket.send_to(buf, to)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for UdpTransport {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.socket.as_raw_fd()
+    }
+}
@@ -0,0 +1,388 @@
+use std::io;
+use std::net::SocketAddr;
+
+use os_socketaddr::OsSocketAddr;
+use tracing::{debug, trace};
+use windows::{
+    Win32::Foundation::*, Win32::Networking::WinSock::*, Win32::System::Threading::*,
+    Win32::System::IO::*,
+};
+
+use super::{ipv4_mapped, normalize_addr, DatagramTransport};
+
+/// State of an overlapped `WSARecvFrom()`.
+///
+/// It is boxed so that the buffer, the peer address and the `OVERLAPPED`
+/// handed to WinSock stay at the same address while the operation is pending.
+struct RecvOp {
+    buf: [u8; 65535],
+    from: OsSocketAddr,
+    from_len: i32,
+    overlapped: OVERLAPPED,
+    pending: bool,
+}
+
+/// State of an overlapped `WSASendTo()`.
+struct SendOp {
+    buf: Vec<u8>,
+    to: OsSocketAddr,
+    overlapped: OVERLAPPED,
+    pending: bool,
+}
+
+/// A `DatagramTransport` backed by a WinSock socket using overlapped I/O.
+///
+/// Completion of a pending receive or send is signaled on the event returned
+/// by `recv_event()` or `send_event()` respectively.
+pub struct WinSockTransport {
+    socket: SOCKET,
+    recv: Box<RecvOp>,
+    send: Box<SendOp>,
+    ipv6: bool,
+}
+
+impl WinSockTransport {
+    /// Binds to `addr`. An IPv6 socket only accepts IPv6 peers.
+    pub fn bind(addr: SocketAddr) -> io::Result<WinSockTransport> {
+        WinSockTransport::bind_socket(addr, false)
+    }
+
+    /// Binds to the IPv6 address `addr`, accepting IPv4 peers too.
+    pub fn bind_dual_stack(addr: SocketAddr) -> io::Result<WinSockTransport> {
+        if addr.is_ipv4() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a dual-stack socket needs an IPv6 address",
+            ));
+        }
+        WinSockTransport::bind_socket(addr, true)
+    }
+
+    fn bind_socket(addr: SocketAddr, dual_stack: bool) -> io::Result<WinSockTransport> {
+        let family = if addr.is_ipv6() { AF_INET6 } else { AF_INET };
+        let socket = unsafe {
+            WSASocketA(
+                family as i32,
+                SOCK_DGRAM as i32,
+                IPPROTO_UDP,
+                std::ptr::null_mut(),
+                0,
+                WSA_FLAG_OVERLAPPED,
+            )
+        };
+        if socket == INVALID_SOCKET {
+            return Err(last_wsa_error());
+        }
+
+        // IPv6 sockets are IPv6-only by default on Windows.
+        if addr.is_ipv6() {
+            let v6_only: u32 = if dual_stack { 0 } else { 1 };
+            let ret = unsafe {
+                setsockopt(
+                    socket,
+                    IPPROTO_IPV6 as i32,
+                    IPV6_V6ONLY as i32,
+                    PSTR(&v6_only as *const u32 as *mut u8),
+                    std::mem::size_of::<u32>() as i32,
+                )
+            };
+            if ret != 0 {
+                let err = last_wsa_error();
+                unsafe { closesocket(socket) };
+                return Err(err);
+            }
+        }
+
+        let addr: OsSocketAddr = addr.into();
+        let ret = unsafe {
+            bind(
+                socket,
+                std::mem::transmute::<*const winapi::shared::ws2def::SOCKADDR, *const SOCKADDR>(
+                    addr.as_ptr(),
+                ),
+                addr.len(),
+            )
+        };
+        if ret != 0 {
+            let err = last_wsa_error();
+            unsafe { closesocket(socket) };
+            return Err(err);
+        }
+
+        Ok(WinSockTransport {
+            socket,
+            recv: Box::new(RecvOp {
+                buf: [0; 65535],
+                from: OsSocketAddr::new(),
+                from_len: 0,
+                overlapped: new_overlapped(),
+                pending: false,
+            }),
+            send: Box::new(SendOp {
+                buf: Vec::with_capacity(65535),
+                to: OsSocketAddr::new(),
+                overlapped: new_overlapped(),
+                pending: false,
+            }),
+            ipv6: addr.is_ipv6(),
+        })
+    }
+
+    pub fn recv_event(&self) -> HANDLE {
+        self.recv.overlapped.hEvent
+    }
+
+    pub fn send_event(&self) -> HANDLE {
+        self.send.overlapped.hEvent
+    }
+
+    /// Checks whether the overlapped operation has completed, without
+    /// waiting for it.
+    fn overlapped_result(&self, overlapped: &OVERLAPPED) -> io::Result<usize> {
+        let mut cbTransfer = 0;
+        let mut dwFlags = 0;
+        let ret = unsafe {
+            WSAGetOverlappedResult(
+                self.socket,
+                overlapped,
+                &mut cbTransfer,
+                false,
+                &mut dwFlags,
+            )
+        };
+        if !ret.as_bool() {
+            let err = unsafe { WSAGetLastError() };
+            if err == WSA_IO_INCOMPLETE {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            return Err(io::Error::from_raw_os_error(err));
+        }
+        Ok(cbTransfer as usize)
+    }
+}
+
+impl DatagramTransport for WinSockTransport {
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let read = if self.recv.pending {
+            // A receive that completed with an error is over too, such as
+            // WSAECONNRESET after an ICMP port unreachable. Leaving it
+            // pending would report that error again on every call.
+            let result = self.overlapped_result(&self.recv.overlapped);
+            if !matches!(&result, Err(e) if e.kind() == io::ErrorKind::WouldBlock) {
+                self.recv.pending = false;
+            }
+            let read = result?;
+            trace!(bytes = read, "WSARecvFrom() completed");
+            read
+        } else {
+            match recvfrom(self.socket, &mut self.recv)? {
+                Some(read) => read,
+
+                None => {
+                    self.recv.pending = true;
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+            }
+        };
+
+        let from = match self.recv.from.into_addr() {
+            Some(v) => normalize_addr(v),
+
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unknown peer address family",
+                ))
+            }
+        };
+
+        let len = std::cmp::min(read, buf.len());
+        buf[..len].copy_from_slice(&self.recv.buf[..len]);
+
+        Ok((len, from))
+    }
+
+    fn send_to(&mut self, buf: &[u8], to: SocketAddr) -> io::Result<usize> {
+        if self.send.pending {
+            match self.overlapped_result(&self.send.overlapped) {
+                Ok(written) => trace!(bytes = written, "WSASendTo() completed"),
+
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(e),
+
+                // Only the earlier datagram is lost, this one is still sent.
+                Err(e) => debug!(error = ?e, "WSASendTo() failed"),
+            }
+            self.send.pending = false;
+        }
+
+        // The datagram is copied so that the caller's buffer can be reused
+        // while the send is pending.
+        self.send.buf.clear();
+        self.send.buf.extend_from_slice(buf);
+        self.send.to = if self.ipv6 { ipv4_mapped(to) } else { to }.into();
+
+        match sendto(self.socket, &mut self.send)? {
+            Some(written) => Ok(written),
+
+            None => {
+                self.send.pending = true;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        let mut addr = OsSocketAddr::new();
+        let mut addr_len = addr.capacity();
+        let ret = unsafe {
+            getsockname(
+                self.socket,
+                std::mem::transmute::<*mut winapi::shared::ws2def::SOCKADDR, *mut SOCKADDR>(
+                    addr.as_mut_ptr(),
+                ),
+                &mut addr_len,
+            )
+        };
+        if ret != 0 {
+            return Err(last_wsa_error());
+        }
+
+        addr.into_addr()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown address family"))
+    }
+}
+
+impl Drop for WinSockTransport {
+    fn drop(&mut self) {
+        unsafe {
+            closesocket(self.socket);
+            CloseHandle(self.recv.overlapped.hEvent);
+            CloseHandle(self.send.overlapped.hEvent);
+        }
+    }
+}
+
+/// Initializes WinSock 2.2. Must be called before any `WinSockTransport` is
+/// created.
+pub fn wsa_startup() -> io::Result<()> {
+    let wVersionRequested: u16 = 2 << 8 | 2;
+    let mut wsaData = WSAData {
+        wVersion: 0,
+        wHighVersion: 0,
+        iMaxSockets: 0,
+        iMaxUdpDg: 0,
+        lpVendorInfo: PSTR(std::ptr::null_mut()),
+        szDescription: [0; 257],
+        szSystemStatus: [0; 129],
+    };
+    let ret = unsafe { WSAStartup(wVersionRequested, &mut wsaData) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    Ok(())
+}
+
+pub fn wsa_cleanup() {
+    unsafe {
+        WSACleanup();
+    }
+}
+
+fn new_overlapped() -> OVERLAPPED {
+    OVERLAPPED {
+        Anonymous: OVERLAPPED_0 {
+            Anonymous: OVERLAPPED_0_0 {
+                Offset: 9,
+                OffsetHigh: 0,
+            },
+        },
+        hEvent: unsafe { CreateEventA(std::ptr::null_mut(), false, false, None) },
+        Internal: 0,
+        InternalHigh: 0,
+    }
+}
+
+fn last_wsa_error() -> io::Error {
+    io::Error::from_raw_os_error(unsafe { WSAGetLastError() })
+}
+
+/// Issues an overlapped `WSARecvFrom()`.
+///
+/// Returns `Some(read)` if the receive completed immediately, or `None` if it
+/// is pending.
+fn recvfrom(socket: SOCKET, op: &mut RecvOp) -> io::Result<Option<usize>> {
+    let mut wsabuf = WSABUF {
+        len: op.buf.len() as u32,
+        buf: PSTR(op.buf.as_mut_ptr()),
+    };
+
+    let mut numberOfBytesRecvd: u32 = 0;
+    let mut flagsRecvd: u32 = 0;
+    op.from_len = op.from.capacity();
+    let ret = unsafe {
+        WSARecvFrom(
+            socket,
+            &mut wsabuf,
+            1u32,
+            &mut numberOfBytesRecvd,
+            &mut flagsRecvd,
+            std::mem::transmute::<*mut winapi::shared::ws2def::SOCKADDR, &mut SOCKADDR>(
+                op.from.as_mut_ptr(),
+            ),
+            &mut op.from_len,
+            &mut op.overlapped,
+            None,
+        )
+    };
+    if ret == 0 {
+        // The event is signaled even on immediate completion, so reset it.
+        unsafe { WaitForSingleObject(op.overlapped.hEvent, 0) };
+        return Ok(Some(numberOfBytesRecvd as usize));
+    }
+
+    let err = unsafe { WSAGetLastError() };
+    if err == WSA_IO_PENDING {
+        trace!("WSARecvFrom() pending");
+        return Ok(None);
+    }
+    Err(io::Error::from_raw_os_error(err))
+}
+
+/// Issues an overlapped `WSASendTo()`.
+///
+/// Returns `Some(written)` if the send completed immediately, or `None` if it
+/// is pending.
+fn sendto(socket: SOCKET, op: &mut SendOp) -> io::Result<Option<usize>> {
+    let mut wsabuf = WSABUF {
+        len: op.buf.len() as u32,
+        buf: PSTR(op.buf.as_mut_ptr()),
+    };
+    let mut numberofbytessent: u32 = 0;
+    let ret = unsafe {
+        WSASendTo(
+            socket,
+            &mut wsabuf,
+            1,
+            &mut numberofbytessent,
+            0,
+            std::mem::transmute::<*const winapi::shared::ws2def::SOCKADDR, *const SOCKADDR>(
+                op.to.as_ptr(),
+            ),
+            op.to.len(),
+            &mut op.overlapped,
+            None,
+        )
+    };
+    if ret == 0 {
+        unsafe { WaitForSingleObject(op.overlapped.hEvent, 0) };
+        trace!(bytes = numberofbytessent, "WSASendTo()");
+        return Ok(Some(numberofbytessent as usize));
+    }
+
+    let err = unsafe { WSAGetLastError() };
+    if err == WSA_IO_PENDING {
+        trace!("WSASendTo() pending");
+        return Ok(None);
+    }
+    Err(io::Error::from_raw_os_error(err))
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use common::{
+    client_config, connect, drain_until_closed, run, server_builder, server_config, TestClient,
+};
+use quic_echo::app::{self, Application};
+
+/// Answers every finished stream with the number of bytes it carried, and
+/// records the callbacks it gets.
+struct Count {
+    events: Arc<Mutex<Vec<String>>>,
+    received: usize,
+}
+
+impl Application for Count {
+    fn on_established(&mut self, conn: &mut quiche::Connection) {
+        let proto = String::from_utf8_lossy(conn.application_proto()).into_owned();
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("established {}", proto));
+    }
+
+    fn on_stream_readable(&mut self, conn: &mut quiche::Connection, stream_id: u64) {
+        let mut buf = [0; 4096];
+        while let Ok((read, fin)) = conn.stream_recv(stream_id, &mut buf) {
+            self.received += read;
+            if fin {
+                let reply = self.received.to_string();
+                conn.stream_send(stream_id, reply.as_bytes(), true).unwrap();
+                self.events
+                    .lock()
+                    .unwrap()
+                    .push(format!("fin {}", stream_id));
+            }
+        }
+    }
+
+    fn on_closed(&mut self, _conn: &quiche::Connection) {
+        self.events.lock().unwrap().push("closed".to_string());
+    }
+}
+
+/// Appends what stream `stream_id` has to `data`, returning whether it is
+/// finished.
+fn read_stream(client: &mut TestClient, stream_id: u64, data: &mut Vec<u8>) -> bool {
+    let mut fin = false;
+    let mut buf = [0; 65535];
+    while let Ok((read, f)) = client.conn.stream_recv(stream_id, &mut buf) {
+        data.extend_from_slice(&buf[..read]);
+        fin = f;
+    }
+    fin
+}
+
+#[test]
+fn custom_application_gets_callbacks() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let factory = {
+        let events = events.clone();
+        app::factory(move || Count {
+            events: events.clone(),
+            received: 0,
+        })
+    };
+
+    let mut config = server_config();
+    config.idle_timeout = 100;
+    let (mut server, mut client) = connect(
+        server_builder().config(config).application(factory),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    client.conn.stream_send(0, &[0; 3000], true).unwrap();
+    let mut reply = Vec::new();
+    run(&mut server, &mut client, |c| read_stream(c, 0, &mut reply));
+    assert_eq!(reply, b"3000");
+
+    drain_until_closed(&mut server, Instant::now() + Duration::from_secs(5));
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["established sample", "fin 0", "closed"]
+    );
+}
+
+#[test]
+fn echo_is_the_default_application() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    client.conn.stream_send(4, b"default", true).unwrap();
+    let mut echoed = Vec::new();
+    run(&mut server, &mut client, |c| read_stream(c, 4, &mut echoed));
+
+    assert_eq!(echoed, b"default");
+}
+
+#[test]
+fn connections_are_routed_by_alpn() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let count = {
+        let events = events.clone();
+        app::factory(move || Count {
+            events: events.clone(),
+            received: 0,
+        })
+    };
+
+    let builder = server_builder()
+        .alpn_application("sample", count)
+        .alpn_application("hq-interop", app::factory(app::Echo::new));
+    let (mut server, mut client) = connect(builder, client_config(quiche::PROTOCOL_VERSION));
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    client.conn.stream_send(0, b"12345", true).unwrap();
+    let mut reply = Vec::new();
+    run(&mut server, &mut client, |c| read_stream(c, 0, &mut reply));
+
+    assert_eq!(reply, b"5");
+    assert_eq!(*events.lock().unwrap(), ["established sample", "fin 0"]);
+}
+
+#[test]
+fn alpn_without_application_is_refused() {
+    let builder = server_builder().alpn_application("hq-interop", app::factory(app::Echo::new));
+    let (mut server, mut client) = connect(builder, client_config(quiche::PROTOCOL_VERSION));
+
+    run(&mut server, &mut client, |c| c.conn.peer_error().is_some());
+
+    let error = client.conn.peer_error().unwrap();
+    assert!(!error.is_app);
+    assert_eq!(error.error_code, app::NO_APPLICATION_PROTOCOL);
+}
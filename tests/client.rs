@@ -0,0 +1,91 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use common::server_builder;
+use quic_echo::event_loop::EventLoop;
+use quic_echo::transport::{DatagramTransport, NativeTransport};
+
+/// Runs `quic_echo_client` with `args` against a server on an ephemeral
+/// port, feeding it `stdin`.
+fn run_client(args: &[&str], stdin: &[u8]) -> Output {
+    let mut event_loop = EventLoop::new().unwrap();
+    let transport = NativeTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = transport.local_addr().unwrap();
+    event_loop
+        .register(server_builder().build(transport).unwrap())
+        .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_quic_echo_client"))
+        .arg(addr.to_string())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The server runs on this thread until the client is done.
+    let shutdown = event_loop.shutdown_handle();
+    let stdin = stdin.to_vec();
+    let waiter = std::thread::spawn(move || {
+        let mut input = child.stdin.take().unwrap();
+        if !stdin.is_empty() {
+            input.write_all(&stdin).unwrap();
+        }
+        drop(input);
+
+        let output = child.wait_with_output().unwrap();
+        shutdown.shutdown();
+        output
+    });
+    event_loop.run().unwrap();
+
+    let output = waiter.join().unwrap();
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn generated_payload_is_verified_on_every_stream() {
+    let output = run_client(&["--streams", "3", "--size", "300000"], b"");
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        report.contains("3 of 3 verified, 300000 bytes each"),
+        "{}",
+        report
+    );
+    for line in ["handshake:", "rtt:", "throughput:"] {
+        assert!(report.contains(line), "{}", report);
+    }
+}
+
+#[test]
+fn stdin_is_sent_as_payload() {
+    let output = run_client(&["--stdin", "--streams", "2"], b"hello from stdin");
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        report.contains("2 of 2 verified, 16 bytes each"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn streams_beyond_the_server_limit_wait_their_turn() {
+    // The server allows 100 concurrent streams.
+    let output = run_client(&["--streams", "250", "--size", "1000"], b"");
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("250 of 250 verified"), "{}", report);
+}
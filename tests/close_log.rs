@@ -0,0 +1,97 @@
+mod common;
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use common::{
+    client_config, connect, drain_until_closed, run, server_builder, server_config, temp_dir,
+    TestClient,
+};
+use quic_echo::close_log::{CloseInitiator, CloseRecord};
+use quic_echo::transport::LoopbackTransport;
+use quic_echo::EchoServer;
+
+/// Delivers what the client has to send, then drives the server until it
+/// collected all its connections.
+fn collect(server: &mut EchoServer<LoopbackTransport>, client: &mut TestClient) {
+    client.flush();
+    server.recv_quic_packets().unwrap();
+    drain_until_closed(server, Instant::now() + Duration::from_secs(5));
+    assert_eq!(server.num_clients(), 0);
+}
+
+#[test]
+fn peer_close_is_recorded() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    let (sink, records) = mpsc::channel();
+    server.set_close_sink(Box::new(sink));
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+    client.conn.close(true, 0x42, b"bye").unwrap();
+    collect(&mut server, &mut client);
+
+    let record = records.try_recv().unwrap();
+    assert_eq!(record.listener, "127.0.0.1:4443".parse().unwrap());
+    assert_eq!(record.peer, "127.0.0.1:50000".parse().unwrap());
+    assert_eq!(record.alpn, "sample");
+    assert!(record.established);
+    assert_eq!(record.closed_by, CloseInitiator::Peer);
+    assert!(record.app_error);
+    assert_eq!(record.error_code, Some(0x42));
+    assert_eq!(record.reason, "bye");
+    assert!(record.bytes_sent > 0 && record.bytes_received > 0);
+    assert!(record.packets_sent > 0 && record.packets_received > 0);
+
+    assert!(records.try_recv().is_err());
+}
+
+#[test]
+fn idle_timeout_is_recorded() {
+    let mut config = server_config();
+    config.idle_timeout = 100;
+    let (mut server, mut client) = connect(
+        server_builder().config(config),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    let (sink, records) = mpsc::channel();
+    server.set_close_sink(Box::new(sink));
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+    collect(&mut server, &mut client);
+
+    let record = records.try_recv().unwrap();
+    assert_eq!(record.closed_by, CloseInitiator::IdleTimeout);
+    assert_eq!(record.error_code, None);
+    assert!(record.duration_ms >= 100);
+}
+
+#[test]
+fn close_log_is_json_lines() {
+    let dir = temp_dir("close_log_is_json_lines");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("closed.jsonl");
+
+    let mut config = server_config();
+    config.close_log = Some(path.clone());
+    let (mut server, mut client) = connect(
+        server_builder().config(config),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+    client.conn.close(false, 0x0, b"").unwrap();
+    collect(&mut server, &mut client);
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    let records: Vec<CloseRecord> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].closed_by, CloseInitiator::Peer);
+    assert!(!records[0].app_error);
+    assert!(content.contains("\"closed_by\":\"peer\""));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,215 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use quic_echo::app::{self, Application, ApplicationFactory};
+use quic_echo::transport::{DatagramTransport, LoopbackTransport};
+use quic_echo::{EchoServer, EchoServerBuilder, ServerConfig};
+
+pub struct TestClient {
+    pub conn: std::pin::Pin<Box<quiche::Connection>>,
+    pub transport: LoopbackTransport,
+    buf: [u8; 65535],
+    out: [u8; 1350],
+}
+
+impl TestClient {
+    pub fn new(
+        conn: std::pin::Pin<Box<quiche::Connection>>,
+        transport: LoopbackTransport,
+    ) -> TestClient {
+        TestClient {
+            conn,
+            transport,
+            buf: [0; 65535],
+            out: [0; 1350],
+        }
+    }
+
+    pub fn flush(&mut self) {
+        loop {
+            let (write, send_info) = match self.conn.send(&mut self.out) {
+                Ok(v) => v,
+
+                Err(quiche::Error::Done) => break,
+
+                Err(e) => panic!("client send failed: {:?}", e),
+            };
+            self.transport
+                .send_to(&self.out[..write], send_info.to)
+                .unwrap();
+        }
+    }
+
+    pub fn recv(&mut self) {
+        while let Ok((read, from)) = self.transport.recv_from(&mut self.buf) {
+            let recv_info = quiche::RecvInfo { from };
+            self.conn.recv(&mut self.buf[..read], recv_info).ok();
+        }
+    }
+}
+
+pub fn server_config() -> ServerConfig {
+    ServerConfig {
+        cert_chain: "tests/cert.crt".into(),
+        priv_key: "tests/cert.key".into(),
+        ..ServerConfig::default()
+    }
+}
+
+pub fn server_builder() -> EchoServerBuilder {
+    EchoServerBuilder::new().config(server_config())
+}
+
+pub fn client_config(version: u32) -> quiche::Config {
+    let mut config = quiche::Config::new(version).unwrap();
+    config.set_application_protos(b"\x06sample").unwrap();
+    config.verify_peer(false);
+    config.set_max_idle_timeout(5000);
+    config.set_initial_max_data(10_000_000);
+    config.set_initial_max_stream_data_bidi_local(1_000_000);
+    config.set_initial_max_stream_data_bidi_remote(1_000_000);
+    config.set_initial_max_streams_bidi(100);
+    config
+}
+
+pub fn connect(
+    builder: EchoServerBuilder,
+    config: quiche::Config,
+) -> (EchoServer<LoopbackTransport>, TestClient) {
+    connect_addrs(
+        builder,
+        config,
+        "127.0.0.1:4443".parse().unwrap(),
+        "127.0.0.1:50000".parse().unwrap(),
+    )
+}
+
+pub fn connect_addrs(
+    builder: EchoServerBuilder,
+    mut config: quiche::Config,
+    server_addr: SocketAddr,
+    client_addr: SocketAddr,
+) -> (EchoServer<LoopbackTransport>, TestClient) {
+    let (server_end, client_end) = LoopbackTransport::pair(server_addr, client_addr);
+
+    let server = builder.build(server_end).unwrap();
+
+    let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    let conn = quiche::connect(None, &scid, server_addr, &mut config).unwrap();
+
+    (server, TestClient::new(conn, client_end))
+}
+
+/// Shuttles packets between the server and the client until `done` returns
+/// true, or panics if that does not happen in a bounded number of round trips.
+pub fn run(
+    server: &mut EchoServer<LoopbackTransport>,
+    client: &mut TestClient,
+    mut done: impl FnMut(&mut TestClient) -> bool,
+) {
+    for _ in 0..100 {
+        client.flush();
+        server.recv_quic_packets().unwrap();
+        server.send_quic_packets().unwrap();
+        client.recv();
+
+        if done(client) {
+            return;
+        }
+    }
+    panic!("client did not make progress");
+}
+
+/// Lets the server's timers fire until it has collected all its connections,
+/// or until `deadline`.
+pub fn drain_until_closed(server: &mut EchoServer<LoopbackTransport>, deadline: Instant) {
+    while server.num_clients() > 0 && Instant::now() < deadline {
+        let timeout = server.timeout().expect("connection timer not armed");
+        std::thread::sleep(std::cmp::min(timeout, Duration::from_millis(50)));
+
+        server.on_timeout();
+        server.send_quic_packets().unwrap();
+        server.remove_closed_connections().unwrap();
+    }
+}
+
+/// A path below the system temp directory that is unique to test `name`,
+/// with whatever an earlier run left there removed.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("quic_echo-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    dir
+}
+
+/// Creates a document root in `temp_dir(name)`, next to a `secret.txt` that
+/// must not be served.
+pub fn document_root(name: &str) -> PathBuf {
+    let dir = temp_dir(name);
+    let root = dir.join("www");
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::write(root.join("index.html"), "<h1>index</h1>").unwrap();
+    std::fs::write(root.join("hello.txt"), "hello, world").unwrap();
+    std::fs::write(root.join("sub").join("index.html"), "sub index").unwrap();
+    std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+    root
+}
+
+/// An application publishing `count` of itself after every callback.
+struct Observed<A> {
+    app: A,
+    count: fn(&A) -> usize,
+    value: Arc<AtomicUsize>,
+}
+
+impl<A: Application> Observed<A> {
+    fn update(&self) {
+        self.value.store((self.count)(&self.app), Ordering::SeqCst);
+    }
+}
+
+impl<A: Application> Application for Observed<A> {
+    fn on_established(&mut self, conn: &mut quiche::Connection) {
+        self.app.on_established(conn);
+        self.update();
+    }
+
+    fn on_stream_readable(&mut self, conn: &mut quiche::Connection, s: u64) {
+        self.app.on_stream_readable(conn, s);
+        self.update();
+    }
+
+    fn on_stream_writable(&mut self, conn: &mut quiche::Connection, s: u64) {
+        self.app.on_stream_writable(conn, s);
+        self.update();
+    }
+
+    fn on_datagram(&mut self, conn: &mut quiche::Connection, data: &[u8]) {
+        self.app.on_datagram(conn, data);
+        self.update();
+    }
+}
+
+/// Wraps a constructor of `A` into a factory whose applications publish
+/// `count` of themselves, e.g. the requests they have in progress, in the
+/// returned counter.
+pub fn observed<A, F>(new_app: F, count: fn(&A) -> usize) -> (ApplicationFactory, Arc<AtomicUsize>)
+where
+    A: Application + 'static,
+    F: Fn() -> A + Send + Sync + 'static,
+{
+    let value = Arc::new(AtomicUsize::new(0));
+    let factory = {
+        let value = value.clone();
+        app::factory(move || Observed {
+            app: new_app(),
+            count,
+            value: value.clone(),
+        })
+    };
+    (factory, value)
+}
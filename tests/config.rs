@@ -0,0 +1,271 @@
+use std::path::Path;
+use std::process::Command;
+
+use quic_echo::config::{parse_listeners, ConfigError};
+
+#[test]
+fn listeners_with_defaults() {
+    let listeners = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+
+        [[listener]]
+        listen = "[::]:4567"
+        cert = "cert.crt"
+        key = "cert.key"
+        alpn = ["sample"]
+        idle_timeout = 30000
+        max_streams_bidi = 10
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap();
+
+    assert_eq!(listeners.len(), 2);
+    assert_eq!(listeners[0].listen, "0.0.0.0:4443".parse().unwrap());
+    assert_eq!(listeners[0].server.idle_timeout, 5000);
+    assert_eq!(listeners[0].server.cert_chain, Path::new("tests/cert.crt"));
+    assert_eq!(listeners[1].server.application_protos, vec!["sample"]);
+    assert_eq!(listeners[1].server.idle_timeout, 30000);
+    assert_eq!(listeners[1].server.max_streams_bidi, 10);
+}
+
+#[test]
+fn unknown_key_is_rejected() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+        idle_timout = 30000
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(matches!(err, ConfigError::Parse(_)));
+    assert!(err.to_string().contains("idle_timout"), "{}", err);
+}
+
+#[test]
+fn bad_address_is_rejected() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0"
+        cert = "cert.crt"
+        key = "cert.key"
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(matches!(err, ConfigError::Parse(_)));
+}
+
+#[test]
+fn missing_cert_file_is_rejected() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "missing.crt"
+        key = "cert.key"
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(matches!(err, ConfigError::Invalid(_)));
+    assert!(err.to_string().contains("missing.crt"), "{}", err);
+}
+
+#[test]
+fn duplicate_address_is_rejected() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn dual_stack_listener() {
+    let listeners = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "[::]:4443"
+        dual_stack = true
+        cert = "cert.crt"
+        key = "cert.key"
+
+        [[listener]]
+        listen = "[::1]:4567"
+        cert = "cert.crt"
+        key = "cert.key"
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap();
+
+    assert!(listeners[0].dual_stack);
+    assert!(!listeners[1].dual_stack);
+}
+
+#[test]
+fn dual_stack_needs_ipv6_address() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        dual_stack = true
+        cert = "cert.crt"
+        key = "cert.key"
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(matches!(err, ConfigError::Invalid(_)));
+    assert!(err.to_string().contains("dual_stack"), "{}", err);
+}
+
+#[test]
+fn dual_stack_wildcard_overlaps_ipv4_listener() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "[::]:4443"
+        dual_stack = true
+        cert = "cert.crt"
+        key = "cert.key"
+
+        [[listener]]
+        listen = "127.0.0.1:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(
+        err.to_string().contains("more than one listener"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn command_line_listeners_are_checked_for_overlaps() {
+    let output = Command::new(env!("CARGO_BIN_EXE_quic_echo"))
+        .args([
+            "--listen",
+            "[::]:4443",
+            "--listen",
+            "0.0.0.0:4443",
+            "--dual-stack",
+        ])
+        .args(["--cert", "tests/cert.crt", "--key", "tests/cert.key"])
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("more than one listener"), "{}", stderr);
+}
+
+#[test]
+fn paths_are_relative_to_config_file() {
+    let listeners = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+        qlog_dir = "qlog"
+        close_log = "closed.jsonl"
+        document_root = "common"
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap();
+
+    let server = &listeners[0].server;
+    let paths = [
+        ("qlog_dir", &server.qlog_dir, "tests/qlog"),
+        ("close_log", &server.close_log, "tests/closed.jsonl"),
+        ("document_root", &server.document_root, "tests/common"),
+    ];
+    for (key, path, expected) in paths {
+        assert_eq!(path.as_deref(), Some(Path::new(expected)), "{}", key);
+    }
+}
+
+#[test]
+fn shutdown_error_code_must_fit_in_62_bits() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+        shutdown_error_code = 4611686018427387904
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("shutdown_error_code"), "{}", err);
+}
+
+#[test]
+fn document_root_must_be_a_directory() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+        document_root = "cert.crt"
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("document_root"), "{}", err);
+}
+
+#[test]
+fn dgram_queues_must_not_be_empty() {
+    let err = parse_listeners(
+        r#"
+        [[listener]]
+        listen = "0.0.0.0:4443"
+        cert = "cert.crt"
+        key = "cert.key"
+        enable_dgram = true
+        dgram_send_queue_len = 0
+        "#,
+        Path::new("tests"),
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("dgram_send_queue_len"), "{}", err);
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use common::{client_config, connect, run, server_builder, server_config, TestClient};
+use quic_echo::transport::LoopbackTransport;
+use quic_echo::EchoServer;
+
+/// Connects a client that accepts datagrams to a server that does too, with
+/// room for `send_queue_len` echoes.
+fn dgram_connect(
+    send_queue_len: usize,
+    client_config: quiche::Config,
+) -> (EchoServer<LoopbackTransport>, TestClient) {
+    let mut config = server_config();
+    config.enable_dgram = true;
+    config.dgram_send_queue_len = send_queue_len;
+
+    let (mut server, mut client) = connect(server_builder().config(config), client_config);
+    run(&mut server, &mut client, |c| c.conn.is_established());
+    (server, client)
+}
+
+fn dgram_client_config() -> quiche::Config {
+    let mut config = client_config(quiche::PROTOCOL_VERSION);
+    config.enable_dgram(true, 100, 100);
+    config
+}
+
+#[test]
+fn datagrams_are_echoed() {
+    let (mut server, mut client) = dgram_connect(1000, dgram_client_config());
+
+    client.conn.dgram_send(b"ping").unwrap();
+    client.conn.dgram_send(b"pong").unwrap();
+
+    let mut echoes = Vec::new();
+    let mut buf = [0; 1350];
+    run(&mut server, &mut client, |c| {
+        while let Ok(len) = c.conn.dgram_recv(&mut buf) {
+            echoes.push(buf[..len].to_vec());
+        }
+        echoes.len() == 2
+    });
+    assert_eq!(echoes, [b"ping".to_vec(), b"pong".to_vec()]);
+
+    let metrics = server.metrics();
+    assert_eq!(metrics.dgram_received.get(), 2);
+    assert_eq!(metrics.dgram_echoed.get(), 2);
+    assert_eq!(metrics.dgram_dropped_queue_full.get(), 0);
+    assert_eq!(metrics.dgram_dropped_oversize.get(), 0);
+}
+
+#[test]
+fn echoes_beyond_the_send_queue_are_dropped() {
+    let (mut server, mut client) = dgram_connect(1, dgram_client_config());
+
+    // All three arrive before the server sends anything, so only the first
+    // echo fits into the queue.
+    for data in [b"one", b"two", b"six"] {
+        client.conn.dgram_send(data).unwrap();
+    }
+    client.flush();
+    server.recv_quic_packets().unwrap();
+    server.send_quic_packets().unwrap();
+    client.recv();
+
+    let mut buf = [0; 1350];
+    let len = client.conn.dgram_recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"one");
+    assert!(client.conn.dgram_recv(&mut buf).is_err());
+
+    let metrics = server.metrics();
+    assert_eq!(metrics.dgram_received.get(), 3);
+    assert_eq!(metrics.dgram_echoed.get(), 1);
+    assert_eq!(metrics.dgram_dropped_queue_full.get(), 2);
+}
+
+#[test]
+fn echoes_larger_than_the_client_accepts_are_dropped() {
+    // The client sends full-size packets but only takes smaller ones, so the
+    // largest datagram it can send cannot come back.
+    let mut config = dgram_client_config();
+    config.set_max_recv_udp_payload_size(1200);
+    let (mut server, mut client) = dgram_connect(1000, config);
+
+    let len = client.conn.dgram_max_writable_len().unwrap();
+    client.conn.dgram_send(&vec![0xab; len]).unwrap();
+    client.flush();
+    server.recv_quic_packets().unwrap();
+    server.send_quic_packets().unwrap();
+
+    let metrics = server.metrics();
+    assert_eq!(metrics.dgram_received.get(), 1);
+    assert_eq!(metrics.dgram_echoed.get(), 0);
+    assert_eq!(metrics.dgram_dropped_oversize.get(), 1);
+}
+
+#[test]
+fn datagrams_are_disabled_by_default() {
+    let (mut server, mut client) = connect(server_builder(), dgram_client_config());
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    assert_eq!(client.conn.dgram_max_writable_len(), None);
+}
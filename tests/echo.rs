@@ -0,0 +1,138 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{client_config, connect, run, server_builder, TestClient};
+use quic_echo::transport::LoopbackTransport;
+use quic_echo::EchoServer;
+
+/// A stream the client sends `data` on, and the echo received so far.
+struct EchoStream {
+    data: Vec<u8>,
+    sent: usize,
+    echoed: Vec<u8>,
+    finished: bool,
+}
+
+impl EchoStream {
+    fn new(len: usize, seed: u8) -> EchoStream {
+        EchoStream {
+            // Not a multiple of any chunk size, so misplaced bytes show up.
+            data: (0..len).map(|i| (i % 251) as u8 ^ seed).collect(),
+            sent: 0,
+            echoed: Vec::new(),
+            finished: false,
+        }
+    }
+}
+
+/// Sends every stream's data, as fast as flow control allows, and reads the
+/// echo until all streams are finished.
+fn echo_streams(
+    server: &mut EchoServer<LoopbackTransport>,
+    client: &mut TestClient,
+    streams: &mut HashMap<u64, EchoStream>,
+) {
+    let mut buf = [0; 65535];
+    for _ in 0..10_000 {
+        for (&s, stream) in streams.iter_mut() {
+            if stream.sent < stream.data.len() {
+                let written = match client
+                    .conn
+                    .stream_send(s, &stream.data[stream.sent..], true)
+                {
+                    Ok(v) => v,
+
+                    Err(quiche::Error::Done) => 0,
+
+                    Err(e) => panic!("client stream send failed: {:?}", e),
+                };
+                stream.sent += written;
+            }
+        }
+
+        client.flush();
+        server.recv_quic_packets().unwrap();
+        server.send_quic_packets().unwrap();
+        client.recv();
+
+        for (&s, stream) in streams.iter_mut() {
+            while let Ok((read, fin)) = client.conn.stream_recv(s, &mut buf) {
+                assert!(!stream.finished, "data after FIN on stream {}", s);
+                stream.echoed.extend_from_slice(&buf[..read]);
+                stream.finished = fin;
+            }
+        }
+
+        if streams.values().all(|s| s.finished) {
+            return;
+        }
+    }
+    panic!("echo did not complete");
+}
+
+#[test]
+fn echo_multi_megabyte_stream() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    let mut streams = HashMap::new();
+    streams.insert(0, EchoStream::new(4 * 1024 * 1024, 0));
+
+    echo_streams(&mut server, &mut client, &mut streams);
+
+    let stream = &streams[&0];
+    assert_eq!(stream.echoed.len(), stream.data.len());
+    assert!(stream.echoed == stream.data);
+}
+
+#[test]
+fn echo_concurrent_streams() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    let mut streams = HashMap::new();
+    for (i, s) in [0, 4, 8, 12].iter().enumerate() {
+        streams.insert(*s, EchoStream::new(1024 * 1024 + i * 4099, i as u8));
+    }
+
+    echo_streams(&mut server, &mut client, &mut streams);
+
+    for (s, stream) in &streams {
+        assert!(stream.echoed == stream.data, "stream {} differs", s);
+    }
+}
+
+#[test]
+fn fin_waits_for_peer_fin() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    let mut echoed = Vec::new();
+    let mut finished = false;
+    let mut buf = [0; 65535];
+
+    client.conn.stream_send(0, b"hello", false).unwrap();
+    run(&mut server, &mut client, |c| {
+        while let Ok((read, fin)) = c.conn.stream_recv(0, &mut buf) {
+            echoed.extend_from_slice(&buf[..read]);
+            finished = fin;
+        }
+        echoed.len() == 5
+    });
+    assert_eq!(echoed, b"hello");
+    assert!(!finished);
+
+    client.conn.stream_send(0, b", world", true).unwrap();
+    run(&mut server, &mut client, |c| {
+        while let Ok((read, fin)) = c.conn.stream_recv(0, &mut buf) {
+            echoed.extend_from_slice(&buf[..read]);
+            finished = fin;
+        }
+        finished
+    });
+    assert_eq!(echoed, b"hello, world");
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use std::error::Error;
+
+use common::{client_config, connect, run, server_builder};
+use quic_echo::transport::{DatagramTransport, LoopbackTransport};
+use quic_echo::EchoServerError;
+
+fn bind() -> LoopbackTransport {
+    let server_addr = "127.0.0.1:4443".parse().unwrap();
+    let client_addr = "127.0.0.1:50000".parse().unwrap();
+    LoopbackTransport::pair(server_addr, client_addr).0
+}
+
+#[test]
+fn missing_certificate_is_reported_with_its_path() {
+    let err = match server_builder()
+        .cert_chain("tests/missing.crt")
+        .build(bind())
+    {
+        Ok(_) => panic!("server built without a certificate"),
+
+        Err(e) => e,
+    };
+
+    assert!(matches!(err, EchoServerError::Quiche(..)));
+    assert!(err.is_fatal());
+    assert!(err.to_string().contains("tests/missing.crt"));
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn invalid_alpn_is_a_config_error() {
+    let err = match server_builder().application_protos(&[""]).build(bind()) {
+        Ok(_) => panic!("server built with an empty ALPN identifier"),
+
+        Err(e) => e,
+    };
+
+    assert!(matches!(err, EchoServerError::Config(_)));
+    assert!(err.is_fatal());
+}
+
+#[test]
+fn garbage_does_not_disturb_established_connections() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    let server_addr = server.transport().local_addr().unwrap();
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    client.transport.send_to(&[0xff; 3], server_addr).unwrap();
+    client
+        .transport
+        .send_to(&[0x40; 1200], server_addr)
+        .unwrap();
+    server.recv_quic_packets().unwrap();
+    assert_eq!(server.metrics().packets_discarded.get(), 2);
+
+    client.conn.stream_send(0, b"still there", true).unwrap();
+    let mut echo = Vec::new();
+    let mut buf = [0; 64];
+    run(&mut server, &mut client, |c| {
+        let mut fin = false;
+        while let Ok((read, f)) = c.conn.stream_recv(0, &mut buf) {
+            echo.extend_from_slice(&buf[..read]);
+            fin = f;
+        }
+        fin
+    });
+    assert_eq!(echo, b"still there");
+    assert_eq!(server.num_clients(), 1);
+}
+
+#[test]
+fn bad_packets_do_not_stop_the_server() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    let server_addr = server.transport().local_addr().unwrap();
+
+    // Not a QUIC packet.
+    client.transport.send_to(&[0xff; 3], server_addr).unwrap();
+    // A short header packet for a connection the server does not know.
+    client.transport.send_to(&[0x40; 64], server_addr).unwrap();
+
+    server.recv_quic_packets().unwrap();
+    server.send_quic_packets().unwrap();
+    assert_eq!(server.num_clients(), 0);
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+    assert_eq!(server.num_clients(), 1);
+}
@@ -0,0 +1,121 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use common::{client_config, server_builder};
+use quic_echo::event_loop::EventLoop;
+use quic_echo::transport::{DatagramTransport, NativeTransport};
+
+/// A quiche client on a real UDP socket.
+struct UdpClient {
+    conn: std::pin::Pin<Box<quiche::Connection>>,
+    socket: UdpSocket,
+}
+
+impl UdpClient {
+    fn connect(server_addr: SocketAddr) -> UdpClient {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+
+        let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+        let mut config = client_config(quiche::PROTOCOL_VERSION);
+        let conn = quiche::connect(None, &scid, server_addr, &mut config).unwrap();
+
+        UdpClient { conn, socket }
+    }
+
+    fn flush(&mut self) {
+        let mut out = [0; 1350];
+        while let Ok((write, send_info)) = self.conn.send(&mut out) {
+            self.socket.send_to(&out[..write], send_info.to).unwrap();
+        }
+    }
+
+    fn recv(&mut self) {
+        let mut buf = [0; 65535];
+        while let Ok((read, from)) = self.socket.recv_from(&mut buf) {
+            let recv_info = quiche::RecvInfo { from };
+            self.conn.recv(&mut buf[..read], recv_info).ok();
+        }
+    }
+}
+
+#[test]
+fn serves_any_number_of_listeners() {
+    let mut event_loop = EventLoop::new().unwrap();
+
+    let mut addrs = Vec::new();
+    for _ in 0..5 {
+        let transport = NativeTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        addrs.push(transport.local_addr().unwrap());
+        event_loop
+            .register(server_builder().build(transport).unwrap())
+            .unwrap();
+    }
+
+    let mut clients: Vec<UdpClient> = addrs.iter().map(|&a| UdpClient::connect(a)).collect();
+
+    for _ in 0..200 {
+        for client in clients.iter_mut() {
+            client.flush();
+        }
+        event_loop.poll(Some(Duration::from_millis(10))).unwrap();
+        for client in clients.iter_mut() {
+            client.recv();
+        }
+
+        if clients.iter().all(|c| c.conn.is_established()) {
+            break;
+        }
+    }
+
+    assert!(clients.iter().all(|c| c.conn.is_established()));
+    for server in event_loop.servers() {
+        assert_eq!(server.num_clients(), 1);
+    }
+}
+
+#[test]
+fn shutdown_handle_stops_run() {
+    let mut event_loop = EventLoop::new().unwrap();
+    let transport = NativeTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = transport.local_addr().unwrap();
+    event_loop
+        .register(server_builder().build(transport).unwrap())
+        .unwrap();
+
+    let mut client = UdpClient::connect(addr);
+    for _ in 0..200 {
+        client.flush();
+        event_loop.poll(Some(Duration::from_millis(10))).unwrap();
+        client.recv();
+
+        if client.conn.is_established() {
+            break;
+        }
+    }
+    assert!(client.conn.is_established());
+
+    let shutdown = event_loop.shutdown_handle();
+    let requester = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        shutdown.shutdown();
+    });
+
+    let start = Instant::now();
+    event_loop.run().unwrap();
+    requester.join().unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(event_loop.servers()[0].num_clients(), 0);
+
+    client.recv();
+    let error = client
+        .conn
+        .peer_error()
+        .expect("no CONNECTION_CLOSE received");
+    assert!(error.is_app);
+}
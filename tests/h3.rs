@@ -0,0 +1,264 @@
+mod common;
+
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use common::{
+    client_config, connect, document_root, observed, run, server_builder, server_config, TestClient,
+};
+use quic_echo::app::H3Server;
+use quic_echo::transport::LoopbackTransport;
+use quic_echo::{EchoServer, EchoServerBuilder};
+use quiche::h3::{self, NameValue};
+
+/// An HTTP/3 client over a `TestClient`.
+struct H3Client {
+    client: TestClient,
+    h3: h3::Connection,
+}
+
+/// A complete response.
+struct Response {
+    headers: Vec<h3::Header>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.name() == name)
+            .map(|h| h.value())
+    }
+}
+
+fn h3_connect(root: Option<&Path>) -> (EchoServer<LoopbackTransport>, H3Client) {
+    let mut config = server_config();
+    config.document_root = root.map(Path::to_path_buf);
+    h3_connect_with(server_builder().config(config))
+}
+
+fn h3_connect_with(builder: EchoServerBuilder) -> (EchoServer<LoopbackTransport>, H3Client) {
+    let mut client_config = client_config(quiche::PROTOCOL_VERSION);
+    client_config.set_application_protos(b"\x02h3").unwrap();
+    client_config.set_initial_max_streams_uni(100);
+    client_config.set_initial_max_stream_data_uni(1_000_000);
+
+    let (mut server, mut client) = connect(builder, client_config);
+    run(&mut server, &mut client, |c| c.conn.is_established());
+    assert_eq!(client.conn.application_proto(), b"h3");
+
+    let h3 = h3::Connection::with_transport(&mut client.conn, &h3::Config::new().unwrap()).unwrap();
+    (server, H3Client { client, h3 })
+}
+
+/// Sends a request with `body`, as fast as flow control allows, and waits
+/// for the whole response and for the whole body to be sent.
+fn request(
+    server: &mut EchoServer<LoopbackTransport>,
+    client: &mut H3Client,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> Response {
+    let headers = [
+        h3::Header::new(":method", method),
+        h3::Header::new(":scheme", "https"),
+        h3::Header::new(":authority", "localhost"),
+        h3::Header::new(":path", path),
+    ];
+    let s = client
+        .h3
+        .send_request(&mut client.client.conn, &headers, body.is_empty())
+        .unwrap();
+
+    let mut sent = 0;
+    let mut finished = false;
+    let mut response = Response {
+        headers: Vec::new(),
+        body: Vec::new(),
+    };
+    let mut buf = [0; 65535];
+    for _ in 0..10_000 {
+        if sent < body.len() {
+            match client
+                .h3
+                .send_body(&mut client.client.conn, s, &body[sent..], true)
+            {
+                Ok(written) => sent += written,
+
+                Err(h3::Error::Done) => (),
+
+                Err(e) => panic!("client send_body failed: {:?}", e),
+            }
+        }
+
+        client.client.flush();
+        server.recv_quic_packets().unwrap();
+        server.send_quic_packets().unwrap();
+        client.client.recv();
+
+        loop {
+            match client.h3.poll(&mut client.client.conn) {
+                Ok((id, h3::Event::Headers { list, .. })) if id == s => response.headers = list,
+
+                Ok((id, h3::Event::Data)) if id == s => {
+                    while let Ok(read) = client.h3.recv_body(&mut client.client.conn, s, &mut buf) {
+                        response.body.extend_from_slice(&buf[..read]);
+                    }
+                }
+
+                Ok((id, h3::Event::Finished)) if id == s => finished = true,
+
+                Ok(_) => (),
+
+                Err(h3::Error::Done) => break,
+
+                Err(e) => panic!("client poll failed: {:?}", e),
+            }
+        }
+
+        if finished && sent == body.len() {
+            return response;
+        }
+    }
+    panic!("response did not complete");
+}
+
+#[test]
+fn get_serves_files() {
+    let root = document_root("h3_get");
+    let (mut server, mut client) = h3_connect(Some(&root));
+
+    let response = request(&mut server, &mut client, "GET", "/hello.txt", b"");
+    assert_eq!(response.header(":status"), Some("200"));
+    assert_eq!(response.header("content-length"), Some("12"));
+    assert_eq!(response.body, b"hello, world");
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
+
+#[test]
+fn get_outside_document_root_is_not_found() {
+    let root = document_root("h3_not_found");
+    let (mut server, mut client) = h3_connect(Some(&root));
+
+    for path in ["/missing.txt", "/../secret.txt"] {
+        let response = request(&mut server, &mut client, "GET", path, b"");
+        assert_eq!(response.header(":status"), Some("404"), "{}", path);
+        assert!(response.body.is_empty());
+    }
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
+
+#[test]
+fn post_echo_streams_body_back() {
+    let (mut server, mut client) = h3_connect(None);
+
+    // Several flow control windows, so the echo is throttled on the way.
+    let body: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+    let response = request(&mut server, &mut client, "POST", "/echo", &body);
+
+    assert_eq!(response.header(":status"), Some("200"));
+    assert!(response.body == body, "got {} bytes", response.body.len());
+}
+
+#[test]
+fn post_echo_without_body() {
+    let (mut server, mut client) = h3_connect(None);
+
+    let response = request(&mut server, &mut client, "POST", "/echo", b"");
+    assert_eq!(response.header(":status"), Some("200"));
+    assert!(response.body.is_empty());
+}
+
+#[test]
+fn other_methods_are_not_allowed() {
+    let (mut server, mut client) = h3_connect(None);
+
+    let response = request(&mut server, &mut client, "DELETE", "/echo", b"");
+    assert_eq!(response.header(":status"), Some("405"));
+    assert_eq!(response.header("allow"), Some("GET, POST"));
+}
+
+#[test]
+fn unused_request_bodies_do_not_stall_the_connection() {
+    let mut config = server_config();
+    config.max_data = 1_000_000;
+    let (mut server, mut client) = h3_connect_with(server_builder().config(config));
+
+    // More than the connection may have in flight, so the server must read
+    // and drop it for the request to complete.
+    let body = vec![0xab; 3_000_000];
+    let response = request(&mut server, &mut client, "POST", "/x", &body);
+    assert_eq!(response.header(":status"), Some("404"));
+
+    let response = request(&mut server, &mut client, "GET", "/hello.txt", b"");
+    assert_eq!(response.header(":status"), Some("404"));
+}
+
+#[test]
+fn reset_requests_drop_their_response() {
+    let (factory, responses) = observed(|| H3Server::new(None), H3Server::responses_in_progress);
+    let (mut server, mut client) =
+        h3_connect_with(server_builder().alpn_application("h3", factory));
+
+    let headers = [
+        h3::Header::new(":method", "POST"),
+        h3::Header::new(":scheme", "https"),
+        h3::Header::new(":authority", "localhost"),
+        h3::Header::new(":path", "/echo"),
+    ];
+    let s = client
+        .h3
+        .send_request(&mut client.client.conn, &headers, false)
+        .unwrap();
+    client
+        .h3
+        .send_body(&mut client.client.conn, s, &[0xab; 10_000], false)
+        .unwrap();
+
+    // Reset the request once part of its echo came back.
+    let mut echoed = 0;
+    let mut buf = [0; 65535];
+    let mut reset = false;
+    for _ in 0..100 {
+        client.client.flush();
+        server.recv_quic_packets().unwrap();
+        server.send_quic_packets().unwrap();
+        client.client.recv();
+
+        loop {
+            match client.h3.poll(&mut client.client.conn) {
+                Ok((id, h3::Event::Data)) if id == s => {
+                    while let Ok(read) = client.h3.recv_body(&mut client.client.conn, s, &mut buf) {
+                        echoed += read;
+                    }
+                }
+
+                Ok(_) => (),
+
+                Err(h3::Error::Done) => break,
+
+                Err(e) => panic!("client poll failed: {:?}", e),
+            }
+        }
+
+        if reset && responses.load(Ordering::SeqCst) == 0 {
+            break;
+        }
+        if !reset && echoed > 0 {
+            assert_eq!(responses.load(Ordering::SeqCst), 1);
+            client
+                .client
+                .conn
+                .stream_shutdown(s, quiche::Shutdown::Write, 0x10c)
+                .unwrap();
+            reset = true;
+        }
+    }
+
+    assert!(reset, "no echo came back");
+    assert_eq!(responses.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,216 @@
+mod common;
+
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use common::{
+    client_config, connect, document_root, observed, run, server_builder, server_config, TestClient,
+};
+use quic_echo::app::HqFileServer;
+use quic_echo::transport::LoopbackTransport;
+use quic_echo::{EchoServer, EchoServerBuilder};
+
+fn hq_connect(root: &Path) -> (EchoServer<LoopbackTransport>, TestClient) {
+    let mut config = server_config();
+    config.document_root = Some(root.to_path_buf());
+    hq_connect_with(server_builder().config(config))
+}
+
+fn hq_connect_with(builder: EchoServerBuilder) -> (EchoServer<LoopbackTransport>, TestClient) {
+    let mut client_config = client_config(quiche::PROTOCOL_VERSION);
+    client_config
+        .set_application_protos(b"\x0ahq-interop")
+        .unwrap();
+
+    let (mut server, mut client) = connect(builder, client_config);
+    run(&mut server, &mut client, |c| c.conn.is_established());
+    assert_eq!(client.conn.application_proto(), b"hq-interop");
+    (server, client)
+}
+
+/// Sends `request` on stream `s` and returns the complete response.
+fn get(
+    server: &mut EchoServer<LoopbackTransport>,
+    client: &mut TestClient,
+    s: u64,
+    request: &str,
+) -> Vec<u8> {
+    client
+        .conn
+        .stream_send(s, request.as_bytes(), true)
+        .unwrap();
+
+    let mut response = Vec::new();
+    let mut buf = [0; 65535];
+    run(server, client, |c| {
+        let mut fin = false;
+        while let Ok((read, f)) = c.conn.stream_recv(s, &mut buf) {
+            response.extend_from_slice(&buf[..read]);
+            fin = f;
+        }
+        fin
+    });
+    response
+}
+
+#[test]
+fn serves_files() {
+    let root = document_root("hq_serves_files");
+    let (mut server, mut client) = hq_connect(&root);
+
+    assert_eq!(
+        get(&mut server, &mut client, 0, "GET /hello.txt\r\n"),
+        b"hello, world"
+    );
+    assert_eq!(
+        get(&mut server, &mut client, 4, "GET /\r\n"),
+        b"<h1>index</h1>"
+    );
+    assert_eq!(
+        get(&mut server, &mut client, 8, "GET /sub/\r\n"),
+        b"sub index"
+    );
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
+
+#[test]
+fn serves_files_larger_than_flow_control_window() {
+    let root = document_root("hq_large_file");
+    let data: Vec<u8> = (0..4_000_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(root.join("large.bin"), &data).unwrap();
+    let (mut server, mut client) = hq_connect(&root);
+
+    client
+        .conn
+        .stream_send(0, b"GET /large.bin\r\n", true)
+        .unwrap();
+
+    // More rounds than `run()` allows, the file takes many flow control
+    // windows.
+    let mut response = Vec::new();
+    let mut buf = [0; 65535];
+    for _ in 0..10_000 {
+        client.flush();
+        server.recv_quic_packets().unwrap();
+        server.send_quic_packets().unwrap();
+        client.recv();
+
+        let mut fin = false;
+        while let Ok((read, f)) = client.conn.stream_recv(0, &mut buf) {
+            response.extend_from_slice(&buf[..read]);
+            fin = f;
+        }
+        if fin {
+            break;
+        }
+    }
+    assert!(response == data, "got {} bytes", response.len());
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
+
+#[test]
+fn missing_files_are_not_found() {
+    let root = document_root("hq_not_found");
+    let (mut server, mut client) = hq_connect(&root);
+
+    assert_eq!(
+        get(&mut server, &mut client, 0, "GET /missing.txt\r\n"),
+        b"Not Found!\r\n"
+    );
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
+
+#[test]
+fn paths_cannot_leave_document_root() {
+    let root = document_root("hq_traversal");
+    let (mut server, mut client) = hq_connect(&root);
+
+    let requests = [
+        "GET /../secret.txt\r\n",
+        "GET /sub/../../secret.txt\r\n",
+        "GET /..%2fsecret.txt\r\n",
+        "GET /..\\secret.txt\r\n",
+        "GET ../secret.txt\r\n",
+    ];
+    for (i, request) in requests.iter().enumerate() {
+        let response = get(&mut server, &mut client, i as u64 * 4, request);
+        assert_eq!(response, b"Not Found!\r\n", "{}", request);
+    }
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
+
+#[test]
+fn other_methods_are_bad_requests() {
+    let root = document_root("hq_bad_request");
+    let (mut server, mut client) = hq_connect(&root);
+
+    assert_eq!(
+        get(&mut server, &mut client, 0, "POST /hello.txt\r\n"),
+        b"Bad Request!\r\n"
+    );
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
+
+#[test]
+fn late_fin_does_not_start_another_request() {
+    let root = document_root("hq_late_fin");
+    let (factory, streams) = {
+        let root = Arc::new(root.clone());
+        observed(
+            move || HqFileServer::new(root.clone()),
+            HqFileServer::streams_in_progress,
+        )
+    };
+    let (mut server, mut client) =
+        hq_connect_with(server_builder().alpn_application("hq-interop", factory));
+
+    // The response is complete before the request is.
+    client
+        .conn
+        .stream_send(0, b"GET /hello.txt\r\n", false)
+        .unwrap();
+    let mut response = Vec::new();
+    let mut buf = [0; 65535];
+    run(&mut server, &mut client, |c| {
+        let mut fin = false;
+        while let Ok((read, f)) = c.conn.stream_recv(0, &mut buf) {
+            response.extend_from_slice(&buf[..read]);
+            fin = f;
+        }
+        fin
+    });
+    assert_eq!(response, b"hello, world");
+    assert_eq!(streams.load(Ordering::SeqCst), 1);
+
+    client.conn.stream_send(0, b"", true).unwrap();
+    run(&mut server, &mut client, |_| {
+        streams.load(Ordering::SeqCst) == 0
+    });
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
+
+#[test]
+fn other_alpns_still_echo() {
+    let root = document_root("hq_other_alpns");
+    let mut config = server_config();
+    config.document_root = Some(root.clone());
+    let (mut server, mut client) = connect(
+        server_builder().config(config),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    assert_eq!(
+        get(&mut server, &mut client, 0, "GET /hello.txt\r\n"),
+        b"GET /hello.txt\r\n"
+    );
+
+    std::fs::remove_dir_all(root.parent().unwrap()).ok();
+}
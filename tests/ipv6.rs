@@ -0,0 +1,77 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use common::{client_config, connect_addrs, run, server_builder};
+use quic_echo::transport::normalize_addr;
+
+#[test]
+fn ipv4_mapped_addresses_are_normalized() {
+    let mapped: SocketAddr = "[::ffff:192.0.2.1]:4433".parse().unwrap();
+    assert_eq!(normalize_addr(mapped), "192.0.2.1:4433".parse().unwrap());
+
+    for addr in ["[::1]:4433", "[2001:db8::1]:4433", "192.0.2.1:4433"] {
+        let addr: SocketAddr = addr.parse().unwrap();
+        assert_eq!(normalize_addr(addr), addr);
+    }
+}
+
+#[test]
+fn handshake_after_retry_over_ipv6() {
+    let (mut server, mut client) = connect_addrs(
+        server_builder(),
+        client_config(quiche::PROTOCOL_VERSION),
+        "[::1]:4443".parse().unwrap(),
+        "[::1]:50000".parse().unwrap(),
+    );
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    assert_eq!(server.num_clients(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn dual_stack_socket_reports_ipv4_peers() {
+    use std::net::UdpSocket;
+
+    use quic_echo::transport::{DatagramTransport, UdpTransport};
+
+    let mut transport = match UdpTransport::bind_dual_stack("[::]:0".parse().unwrap()) {
+        Ok(v) => v,
+
+        // No IPv6 support on this host.
+        Err(_) => return,
+    };
+    let port = transport.local_addr().unwrap().port();
+
+    let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+    peer.send_to(b"ping", ("127.0.0.1", port)).unwrap();
+
+    let mut buf = [0; 16];
+    let (read, from) = loop {
+        match transport.recv_from(&mut buf) {
+            Ok(v) => break v,
+
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(std::time::Duration::from_millis(1))
+            }
+
+            Err(e) => panic!("recv_from() failed: {:?}", e),
+        }
+    };
+    assert_eq!(&buf[..read], b"ping");
+    assert_eq!(from, peer.local_addr().unwrap());
+
+    // Replies to the IPv4 address reach the peer.
+    transport.send_to(b"pong", from).unwrap();
+    let (read, _) = peer.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..read], b"pong");
+}
+
+#[test]
+fn dual_stack_needs_ipv6_address() {
+    use quic_echo::transport::UdpTransport;
+
+    assert!(UdpTransport::bind_dual_stack("127.0.0.1:0".parse().unwrap()).is_err());
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use common::{client_config, connect, run, server_builder};
+use quic_echo::logging::{self, LogFormat};
+
+/// Collects log output in memory.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    fn lines(&self) -> Vec<String> {
+        let output = self.0.lock().unwrap();
+        String::from_utf8_lossy(&output)
+            .lines()
+            .map(|l| l.to_string())
+            .collect()
+    }
+}
+
+/// Logs a handshake with `format` and returns the output.
+fn handshake_log(format: LogFormat, directives: &str) -> Vec<String> {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let filter = logging::filter(Some(directives)).unwrap();
+    let subscriber = logging::subscriber(format, filter, move || writer.clone());
+
+    tracing::subscriber::with_default(subscriber, || {
+        let (mut server, mut client) =
+            connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+        run(&mut server, &mut client, |c| c.conn.is_established());
+    });
+
+    captured.lines()
+}
+
+#[test]
+fn json_lines_carry_connection_context() {
+    let lines = handshake_log(LogFormat::Json, "quic_echo=info");
+
+    let line = lines
+        .iter()
+        .find(|l| l.contains("new connection"))
+        .expect("no new connection event");
+    assert!(line.starts_with('{') && line.ends_with('}'), "{}", line);
+    assert!(line.contains(r#""name":"conn""#), "{}", line);
+    assert!(line.contains(r#""trace_id":""#), "{}", line);
+    assert!(line.contains(r#""listener":"127.0.0.1:4443""#), "{}", line);
+    assert!(line.contains(r#""peer":"127.0.0.1:50000""#), "{}", line);
+}
+
+#[test]
+fn text_lines_carry_connection_context() {
+    let lines = handshake_log(LogFormat::Text, "quic_echo=info");
+
+    let line = lines
+        .iter()
+        .find(|l| l.contains("new connection"))
+        .expect("no new connection event");
+    assert!(line.contains("INFO"), "{}", line);
+    assert!(line.contains("conn{trace_id="), "{}", line);
+    assert!(line.contains("listener=127.0.0.1:4443"), "{}", line);
+    assert!(line.contains("peer=127.0.0.1:50000"), "{}", line);
+}
+
+#[test]
+fn level_filters_per_packet_events() {
+    let info = handshake_log(LogFormat::Text, "quic_echo=info");
+    assert!(!info.iter().any(|l| l.contains("TRACE")));
+
+    let trace = handshake_log(LogFormat::Text, "quic_echo=trace");
+    assert!(trace.iter().any(|l| l.contains("TRACE")));
+}
+
+#[test]
+fn log_format_names() {
+    assert_eq!("text".parse(), Ok(LogFormat::Text));
+    assert_eq!("json".parse(), Ok(LogFormat::Json));
+    assert!("yaml".parse::<LogFormat>().is_err());
+    assert!(logging::filter(Some("quic_echo=loud")).is_err());
+}
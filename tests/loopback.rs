@@ -0,0 +1,46 @@
+mod common;
+
+use common::{client_config, connect, run, server_builder};
+
+#[test]
+fn handshake_after_retry() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    assert_eq!(server.num_clients(), 1);
+    assert_eq!(client.conn.application_proto(), b"sample");
+}
+
+#[test]
+fn handshake_after_version_negotiation() {
+    let (mut server, mut client) = connect(server_builder(), client_config(0xbabababa));
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    assert_eq!(server.num_clients(), 1);
+}
+
+#[test]
+fn echo_stream_data() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    client.conn.stream_send(0, b"hello, echo", true).unwrap();
+
+    let mut echoed = Vec::new();
+    let mut buf = [0; 65535];
+    run(&mut server, &mut client, |c| {
+        let mut fin = false;
+        while let Ok((read, f)) = c.conn.stream_recv(0, &mut buf) {
+            echoed.extend_from_slice(&buf[..read]);
+            fin = f;
+        }
+        fin
+    });
+
+    assert_eq!(echoed, b"hello, echo");
+}
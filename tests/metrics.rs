@@ -0,0 +1,118 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use common::{client_config, connect, drain_until_closed, run, server_builder, server_config};
+use quic_echo::metrics::{self, Registry};
+use quic_echo::transport::DatagramTransport;
+
+fn http_get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn handshake_is_counted() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    let metrics = server.metrics();
+    assert_eq!(metrics.retry_sent.get(), 1);
+    assert_eq!(metrics.version_negotiation_sent.get(), 0);
+    assert_eq!(metrics.connections_accepted.get(), 1);
+    assert_eq!(metrics.connections_active.get(), 1);
+    assert_eq!(metrics.connections_half_open.get(), 0);
+    assert!(metrics.datagrams_received.get() >= 2);
+    assert!(metrics.bytes_sent.get() > 0);
+}
+
+#[test]
+fn version_negotiation_is_counted() {
+    let (mut server, mut client) = connect(server_builder(), client_config(0xbabababa));
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    assert_eq!(server.metrics().version_negotiation_sent.get(), 1);
+    assert_eq!(server.metrics().retry_sent.get(), 1);
+}
+
+#[test]
+fn bad_packets_are_counted_as_discarded() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    let server_addr = server.transport().local_addr().unwrap();
+
+    client.transport.send_to(&[0xff; 3], server_addr).unwrap();
+    client.transport.send_to(&[0x40; 64], server_addr).unwrap();
+    server.recv_quic_packets().unwrap();
+
+    assert_eq!(server.metrics().packets_discarded.get(), 2);
+    assert_eq!(server.metrics().datagrams_received.get(), 2);
+}
+
+#[test]
+fn closed_connections_add_their_stats() {
+    let mut config = server_config();
+    config.idle_timeout = 100;
+    let (mut server, mut client) = connect(
+        server_builder().config(config),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    drain_until_closed(&mut server, Instant::now() + Duration::from_secs(5));
+
+    let metrics = server.metrics();
+    assert_eq!(metrics.connections_closed.get(), 1);
+    assert_eq!(metrics.connections_active.get(), 0);
+    assert!(metrics.quic_packets_received.get() > 0);
+    assert!(metrics.quic_packets_sent.get() > 0);
+}
+
+#[test]
+fn render_labels_each_listener() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    let registry = Registry::new();
+    registry.register("127.0.0.1:4443".parse().unwrap(), server.metrics().clone());
+    let text = registry.render();
+
+    assert!(text.contains("# TYPE quic_echo_connections_active gauge\n"));
+    assert!(text.contains("# TYPE quic_echo_retry_sent_total counter\n"));
+    assert!(text.contains("quic_echo_connections_active{listener=\"127.0.0.1:4443\"} 1\n"));
+    assert!(text.contains("quic_echo_retry_sent_total{listener=\"127.0.0.1:4443\"} 1\n"));
+}
+
+#[test]
+fn endpoint_serves_metrics() {
+    let (server, _client) = connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+    server.metrics().connections_accepted.add(3);
+
+    let registry = Registry::new();
+    registry.register("127.0.0.1:4443".parse().unwrap(), server.metrics().clone());
+    let (addr, _) = metrics::serve("127.0.0.1:0".parse().unwrap(), registry).unwrap();
+
+    let response = http_get(addr, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+    assert!(
+        response.contains("quic_echo_connections_accepted_total{listener=\"127.0.0.1:4443\"} 3\n")
+    );
+
+    let response = http_get(addr, "/");
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{}",
+        response
+    );
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{client_config, connect, run, server_builder, server_config, temp_dir};
+
+#[test]
+fn qlog_file_per_connection() {
+    let dir = temp_dir("qlog_file_per_connection");
+    let mut config = server_config();
+    config.qlog_dir = Some(dir.clone());
+
+    let (mut server, mut client) = connect(
+        server_builder().config(config),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    // Dropping the connections flushes their qlog.
+    drop(server);
+
+    let files: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+
+    let file = &files[0];
+    assert_eq!(file.extension().unwrap(), "sqlog");
+    let trace_id = file.file_stem().unwrap().to_str().unwrap();
+    assert!(
+        trace_id.chars().all(|c| c.is_ascii_hexdigit()),
+        "{}",
+        trace_id
+    );
+
+    let qlog = std::fs::read_to_string(file).unwrap();
+    assert!(qlog.contains("quic_echo server qlog"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::{client_config, connect, run, server_builder, server_config};
+use quic_echo::retry::RetryPolicy;
+use quic_echo::transport::DatagramTransport;
+use quic_echo::EchoServerBuilder;
+
+fn builder(policy: RetryPolicy, max_half_open: usize, max_initial_rate: u32) -> EchoServerBuilder {
+    let mut config = server_config();
+    config.retry_policy = policy;
+    config.retry_max_half_open = max_half_open;
+    config.retry_max_initial_rate = max_initial_rate;
+    server_builder().config(config)
+}
+
+/// Sends the first Initial packet of a second connection from the same
+/// client address.
+fn send_second_initial(client: &mut common::TestClient, server_addr: std::net::SocketAddr) {
+    let scid = quiche::ConnectionId::from_ref(&[0xcd; 16]);
+    let mut config = client_config(quiche::PROTOCOL_VERSION);
+    let mut conn = quiche::connect(None, &scid, server_addr, &mut config).unwrap();
+
+    let mut out = [0; 1350];
+    let (write, send_info) = conn.send(&mut out).unwrap();
+    client
+        .transport
+        .send_to(&out[..write], send_info.to)
+        .unwrap();
+}
+
+#[test]
+fn always_retries_first_initial() {
+    let (mut server, mut client) = connect(
+        builder(RetryPolicy::Always, 100, 10),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+
+    client.flush();
+    server.recv_quic_packets().unwrap();
+
+    assert_eq!(server.num_clients(), 0);
+    assert_eq!(client.transport.pending(), 1);
+}
+
+#[test]
+fn never_accepts_first_initial() {
+    let (mut server, mut client) = connect(
+        builder(RetryPolicy::Never, 100, 10),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+
+    client.flush();
+    server.recv_quic_packets().unwrap();
+    assert_eq!(server.num_clients(), 1);
+
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    client.conn.stream_send(0, b"no retry", true).unwrap();
+
+    let mut echoed = Vec::new();
+    let mut buf = [0; 65535];
+    run(&mut server, &mut client, |c| {
+        let mut fin = false;
+        while let Ok((read, f)) = c.conn.stream_recv(0, &mut buf) {
+            echoed.extend_from_slice(&buf[..read]);
+            fin = f;
+        }
+        fin
+    });
+    assert_eq!(echoed, b"no retry");
+}
+
+#[test]
+fn under_load_retries_above_half_open_threshold() {
+    let (mut server, mut client) = connect(
+        builder(RetryPolicy::UnderLoad, 1, 10),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    let server_addr = server.transport().local_addr().unwrap();
+
+    client.flush();
+    server.recv_quic_packets().unwrap();
+    assert_eq!(server.num_clients(), 1);
+
+    send_second_initial(&mut client, server_addr);
+    server.recv_quic_packets().unwrap();
+    assert_eq!(server.num_clients(), 1);
+}
+
+#[test]
+fn under_load_retries_above_initial_rate() {
+    let (mut server, mut client) = connect(
+        builder(RetryPolicy::UnderLoad, 100, 1),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    let server_addr = server.transport().local_addr().unwrap();
+
+    client.flush();
+    server.recv_quic_packets().unwrap();
+    assert_eq!(server.num_clients(), 1);
+
+    send_second_initial(&mut client, server_addr);
+    server.recv_quic_packets().unwrap();
+    assert_eq!(server.num_clients(), 1);
+}
+
+#[test]
+fn under_load_accepts_below_thresholds() {
+    let (mut server, mut client) = connect(
+        builder(RetryPolicy::UnderLoad, 100, 10),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    let server_addr = server.transport().local_addr().unwrap();
+
+    client.flush();
+    server.recv_quic_packets().unwrap();
+    send_second_initial(&mut client, server_addr);
+    server.recv_quic_packets().unwrap();
+
+    assert_eq!(server.num_clients(), 2);
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use std::io;
+use std::net::SocketAddr;
+
+use common::{client_config, server_config, TestClient};
+use quic_echo::retry::RetryPolicy;
+use quic_echo::send_queue::{SendQueue, SendQueueStats};
+use quic_echo::transport::{DatagramTransport, LoopbackTransport};
+use quic_echo::{EchoServer, EchoServerBuilder, EchoServerError};
+
+/// A loopback transport that only accepts `budget` more datagrams before
+/// returning `WouldBlock`, like a socket whose send buffer is full.
+struct ThrottledTransport {
+    inner: LoopbackTransport,
+    budget: usize,
+}
+
+impl DatagramTransport for ThrottledTransport {
+    fn recv_from(&mut self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inner.recv_from(buf)
+    }
+
+    fn send_to(&mut self, buf: &[u8], to: SocketAddr) -> io::Result<usize> {
+        if self.budget == 0 {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        self.budget -= 1;
+        self.inner.send_to(buf, to)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+fn throttled_pair(budget: usize) -> (ThrottledTransport, LoopbackTransport) {
+    let a: SocketAddr = "127.0.0.1:4443".parse().unwrap();
+    let b: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+    let (a, b) = LoopbackTransport::pair(a, b);
+
+    (ThrottledTransport { inner: a, budget }, b)
+}
+
+fn received(transport: &mut LoopbackTransport) -> Vec<Vec<u8>> {
+    let mut buf = [0; 65535];
+    let mut datagrams = Vec::new();
+    while let Ok((read, _)) = transport.recv_from(&mut buf) {
+        datagrams.push(buf[..read].to_vec());
+    }
+    datagrams
+}
+
+#[test]
+fn queues_while_transport_is_busy() {
+    let (mut transport, mut peer) = throttled_pair(1);
+    let to = peer.local_addr().unwrap();
+    let mut queue = SendQueue::new(4);
+
+    for i in 0..3u8 {
+        assert!(queue.send(&mut transport, &[i], to).unwrap());
+    }
+
+    assert_eq!(received(&mut peer), vec![vec![0]]);
+    assert_eq!(
+        queue.stats(),
+        SendQueueStats {
+            queued: 2,
+            peak: 2,
+            dropped: 0
+        }
+    );
+
+    // Queued datagrams go first, even when the transport could take the new
+    // one.
+    transport.budget = 1;
+    queue.send(&mut transport, &[3], to).unwrap();
+    assert_eq!(received(&mut peer), Vec::<Vec<u8>>::new());
+
+    transport.budget = 10;
+    queue.flush(&mut transport).unwrap();
+    assert_eq!(received(&mut peer), vec![vec![1], vec![2], vec![3]]);
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn drops_when_full() {
+    let (mut transport, mut peer) = throttled_pair(0);
+    let to = peer.local_addr().unwrap();
+    let mut queue = SendQueue::new(2);
+
+    let kept: Vec<bool> = (0..5u8)
+        .map(|i| queue.send(&mut transport, &[i], to).unwrap())
+        .collect();
+    assert_eq!(kept, [true, true, false, false, false]);
+    assert!(queue.is_full());
+
+    let err = queue.flush(&mut transport).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+    transport.budget = 10;
+    queue.flush(&mut transport).unwrap();
+
+    assert_eq!(received(&mut peer), vec![vec![0], vec![1]]);
+    assert_eq!(
+        queue.stats(),
+        SendQueueStats {
+            queued: 0,
+            peak: 2,
+            dropped: 3
+        }
+    );
+}
+
+#[test]
+fn server_keeps_packets_while_transport_is_busy() {
+    let (server_end, client_end) = throttled_pair(0);
+    let server_addr = server_end.local_addr().unwrap();
+
+    let mut config = server_config();
+    config.send_queue_capacity = 2;
+    let mut server: EchoServer<ThrottledTransport> = EchoServerBuilder::new()
+        .config(config)
+        .build(server_end)
+        .unwrap();
+
+    let mut client_config = client_config(quiche::PROTOCOL_VERSION);
+    let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    let conn = quiche::connect(None, &scid, server_addr, &mut client_config).unwrap();
+    let mut client = TestClient::new(conn, client_end);
+
+    // The server can send one datagram per round, so the handshake flight
+    // has to wait in the queue and in quiche.
+    for _ in 0..200 {
+        client.flush();
+        server.recv_quic_packets().unwrap();
+        match server.send_quic_packets() {
+            Ok(()) | Err(EchoServerError::SendPending) => (),
+
+            Err(e) => panic!("send_quic_packets() failed: {:?}", e),
+        }
+        client.recv();
+        server.transport_mut().budget = 1;
+
+        if client.conn.is_established() {
+            break;
+        }
+    }
+
+    assert!(client.conn.is_established());
+
+    let stats = server.send_queue_stats();
+    assert!(stats.peak > 0);
+    assert!(stats.peak <= 2);
+    assert_eq!(stats.dropped, 0);
+}
+
+#[test]
+fn server_counts_only_replies_it_kept() {
+    let (server_end, mut client_end) = throttled_pair(0);
+    let server_addr = server_end.local_addr().unwrap();
+
+    let mut config = server_config();
+    config.send_queue_capacity = 1;
+    config.retry_policy = RetryPolicy::Always;
+    let mut server: EchoServer<ThrottledTransport> = EchoServerBuilder::new()
+        .config(config)
+        .build(server_end)
+        .unwrap();
+
+    // The same Initial twice: the first Retry is queued, the second dropped.
+    let mut client_config = client_config(quiche::PROTOCOL_VERSION);
+    let scid = quiche::ConnectionId::from_ref(&[0xba; 16]);
+    let mut conn = quiche::connect(None, &scid, server_addr, &mut client_config).unwrap();
+    let mut out = [0; 1350];
+    let (write, _) = conn.send(&mut out).unwrap();
+    for _ in 0..2 {
+        client_end.send_to(&out[..write], server_addr).unwrap();
+    }
+    server.recv_quic_packets().unwrap();
+
+    let metrics = server.metrics();
+    assert_eq!(metrics.datagrams_sent.get(), 1);
+    assert_eq!(server.send_queue_stats().dropped, 1);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{client_config, connect, drain_until_closed, run, server_builder, server_config};
+
+#[test]
+fn shutdown_closes_connections_with_error_code() {
+    let mut config = server_config();
+    config.shutdown_error_code = 0x17;
+    let (mut server, mut client) = connect(
+        server_builder().config(config),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    run(&mut server, &mut client, |c| c.conn.is_established());
+
+    server.shutdown();
+    server.send_quic_packets().unwrap();
+    client.recv();
+
+    let error = client
+        .conn
+        .peer_error()
+        .expect("no CONNECTION_CLOSE received");
+    assert!(error.is_app);
+    assert_eq!(error.error_code, 0x17);
+
+    // The server keeps the connection for its closing period only.
+    drain_until_closed(&mut server, Instant::now() + Duration::from_secs(5));
+    assert_eq!(server.num_clients(), 0);
+}
+
+#[test]
+fn no_connection_is_accepted_after_shutdown() {
+    let (mut server, mut client) =
+        connect(server_builder(), client_config(quiche::PROTOCOL_VERSION));
+
+    server.shutdown();
+    client.flush();
+    server.recv_quic_packets().unwrap();
+    server.send_quic_packets().unwrap();
+
+    assert_eq!(server.num_clients(), 0);
+    assert_eq!(client.transport.pending(), 0);
+    assert_eq!(server.metrics().packets_discarded.get(), 1);
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{client_config, connect, drain_until_closed, run, server_builder, server_config};
+use quic_echo::timer::TimerWheel;
+
+const MS: Duration = Duration::from_millis(1);
+
+#[test]
+fn fires_only_expired_timers() {
+    let start = Instant::now();
+    let mut timers = TimerWheel::new(MS, 64, start);
+
+    timers.set("a", start + 5 * MS);
+    timers.set("b", start + 10 * MS);
+    timers.set("c", start + 20 * MS);
+
+    assert_eq!(timers.expire(start + 4 * MS), Vec::<&str>::new());
+    assert_eq!(timers.expire(start + 12 * MS), vec!["a", "b"]);
+    assert_eq!(timers.len(), 1);
+    assert_eq!(timers.expire(start + 20 * MS), vec!["c"]);
+    assert!(timers.is_empty());
+}
+
+#[test]
+fn never_fires_early() {
+    let start = Instant::now();
+    let mut timers = TimerWheel::new(MS, 64, start);
+
+    // Halfway through a tick.
+    timers.set("a", start + 5 * MS + MS / 2);
+
+    assert_eq!(timers.expire(start + 5 * MS), Vec::<&str>::new());
+    assert_eq!(timers.expire(start + 6 * MS), vec!["a"]);
+}
+
+#[test]
+fn rearming_replaces_deadline() {
+    let start = Instant::now();
+    let mut timers = TimerWheel::new(MS, 64, start);
+
+    timers.set("a", start + 5 * MS);
+    timers.set("a", start + 30 * MS);
+    assert_eq!(timers.deadline(&"a"), Some(start + 30 * MS));
+
+    assert_eq!(timers.expire(start + 10 * MS), Vec::<&str>::new());
+    assert_eq!(timers.expire(start + 30 * MS), vec!["a"]);
+
+    timers.set("b", start + 40 * MS);
+    timers.cancel(&"b");
+    assert_eq!(timers.expire(start + 50 * MS), Vec::<&str>::new());
+    assert_eq!(timers.next_expiry(), None);
+}
+
+#[test]
+fn rearming_does_not_pile_up_entries() {
+    let start = Instant::now();
+    let mut timers = TimerWheel::new(MS, 64, start);
+
+    // Deadlines within one tick share an entry.
+    for ns in 0..10_000 {
+        timers.set("a", start + 5 * MS - Duration::from_nanos(ns));
+    }
+    assert_eq!(timers.entries(), 1);
+    assert_eq!(timers.deadline(&"a"), Some(start + 5 * MS));
+
+    // Re-arming 30 ms ahead as time passes leaves at most one entry per tick
+    // until the deadline.
+    for ms in 0..1000 {
+        let now = start + ms * MS;
+        timers.expire(now);
+        for ns in 0..100 {
+            timers.set("a", now + 30 * MS - Duration::from_nanos(ns));
+        }
+        assert!(timers.entries() <= 31);
+    }
+    assert_eq!(timers.len(), 1);
+}
+
+#[test]
+fn deadlines_beyond_one_turn() {
+    let start = Instant::now();
+    let mut timers = TimerWheel::new(MS, 8, start);
+
+    // Shares a slot with a deadline 3 ms away.
+    timers.set("far", start + 19 * MS);
+    timers.set("near", start + 3 * MS);
+
+    assert_eq!(timers.next_expiry(), Some(start + 3 * MS));
+    assert_eq!(timers.expire(start + 3 * MS), vec!["near"]);
+    assert_eq!(timers.expire(start + 11 * MS), Vec::<&str>::new());
+    assert_eq!(timers.expire(start + 18 * MS), Vec::<&str>::new());
+
+    // Expiring long after a full turn still finds it.
+    assert_eq!(timers.expire(start + 100 * MS), vec!["far"]);
+}
+
+#[test]
+fn past_deadline_fires_on_next_expiry() {
+    let start = Instant::now();
+    let mut timers = TimerWheel::new(MS, 8, start);
+
+    assert!(timers.expire(start + 10 * MS).is_empty());
+    timers.set("late", start + 2 * MS);
+
+    assert_eq!(timers.expire(start + 11 * MS), vec!["late"]);
+}
+
+#[test]
+fn many_timers() {
+    let start = Instant::now();
+    let mut timers = TimerWheel::new(MS, 1024, start);
+
+    for i in 0..50_000u32 {
+        timers.set(i, start + (i % 5000) * MS);
+    }
+    // Re-arm half of them further away.
+    for i in (0..50_000u32).step_by(2) {
+        timers.set(i, start + Duration::from_secs(10) + (i % 5000) * MS);
+    }
+
+    let mut fired = 0;
+    for ms in 0..5000u32 {
+        let expired = timers.expire(start + ms * MS);
+        assert!(expired.iter().all(|i| i % 2 == 1 && i % 5000 <= ms));
+        fired += expired.len();
+    }
+    assert_eq!(fired, 25_000);
+    assert_eq!(timers.len(), 25_000);
+}
+
+#[test]
+fn idle_connection_is_closed_by_its_timer() {
+    let mut config = server_config();
+    config.idle_timeout = 100;
+    let (mut server, mut client) = connect(
+        server_builder().config(config),
+        client_config(quiche::PROTOCOL_VERSION),
+    );
+    run(&mut server, &mut client, |c| c.conn.is_established());
+    assert_eq!(server.num_clients(), 1);
+
+    drain_until_closed(&mut server, Instant::now() + Duration::from_secs(5));
+
+    assert_eq!(server.num_clients(), 0);
+    assert_eq!(server.timeout(), None);
+}
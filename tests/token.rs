@@ -0,0 +1,118 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+use quic_echo::token::{RetryTokens, TokenKey};
+
+fn client() -> SocketAddr {
+    "192.0.2.1:50000".parse().unwrap()
+}
+
+fn tokens() -> RetryTokens {
+    RetryTokens::with_key(
+        TokenKey::new(&[7; 16]).unwrap(),
+        Duration::from_secs(10),
+        Duration::from_secs(60),
+    )
+}
+
+#[test]
+fn valid_token_returns_odcid() {
+    let tokens = tokens();
+    let token = tokens.mint(b"original dcid", &client()).unwrap();
+
+    let odcid = tokens.validate(&client(), &token).unwrap();
+    assert_eq!(odcid.as_ref(), b"original dcid");
+}
+
+#[test]
+fn token_from_other_address_is_rejected() {
+    let tokens = tokens();
+    let token = tokens.mint(b"original dcid", &client()).unwrap();
+
+    let other_ip: SocketAddr = "192.0.2.2:50000".parse().unwrap();
+    let other_port: SocketAddr = "192.0.2.1:50001".parse().unwrap();
+    assert!(tokens.validate(&other_ip, &token).is_none());
+    assert!(tokens.validate(&other_port, &token).is_none());
+}
+
+#[test]
+fn tampered_token_is_rejected() {
+    let tokens = tokens();
+    let token = tokens.mint(b"original dcid", &client()).unwrap();
+
+    for i in 0..token.len() {
+        let mut tampered = token.clone();
+        tampered[i] ^= 0x01;
+        assert!(tokens.validate(&client(), &tampered).is_none());
+    }
+    assert!(tokens
+        .validate(&client(), &token[..token.len() - 1])
+        .is_none());
+    assert!(tokens.validate(&client(), b"quiche").is_none());
+}
+
+#[test]
+fn token_from_other_key_is_rejected() {
+    let token = tokens().mint(b"original dcid", &client()).unwrap();
+
+    let other = RetryTokens::with_key(
+        TokenKey::new(&[8; 16]).unwrap(),
+        Duration::from_secs(10),
+        Duration::from_secs(60),
+    );
+    assert!(other.validate(&client(), &token).is_none());
+}
+
+#[test]
+fn expired_token_is_rejected() {
+    let tokens = tokens();
+    let issued = SystemTime::now();
+    let token = tokens.mint_at(b"original dcid", &client(), issued).unwrap();
+
+    let later = issued + Duration::from_secs(5);
+    assert!(tokens.validate_at(&client(), &token, later).is_some());
+
+    let expired = issued + Duration::from_secs(11);
+    assert!(tokens.validate_at(&client(), &token, expired).is_none());
+}
+
+#[test]
+fn token_survives_one_rotation() {
+    let mut tokens = tokens();
+    let token = tokens.mint(b"original dcid", &client()).unwrap();
+
+    tokens.rotate_to(TokenKey::new(&[8; 16]).unwrap());
+    assert!(tokens.validate(&client(), &token).is_some());
+
+    let fresh = tokens.mint(b"original dcid", &client()).unwrap();
+    assert!(tokens.validate(&client(), &fresh).is_some());
+
+    tokens.rotate_to(TokenKey::new(&[9; 16]).unwrap());
+    assert!(tokens.validate(&client(), &token).is_none());
+    assert!(tokens.validate(&client(), &fresh).is_some());
+}
+
+#[test]
+fn rotation_follows_interval() {
+    let mut tokens = tokens();
+    let start = Instant::now();
+    let token = tokens.mint(b"original dcid", &client()).unwrap();
+
+    tokens
+        .rotate_if_due(start + Duration::from_secs(30))
+        .unwrap();
+    let unrotated = tokens.mint(b"original dcid", &client()).unwrap();
+
+    // One rotation: tokens from the old key are still accepted.
+    tokens
+        .rotate_if_due(start + Duration::from_secs(61))
+        .unwrap();
+    assert!(tokens.validate(&client(), &token).is_some());
+    assert!(tokens.validate(&client(), &unrotated).is_some());
+
+    // Two intervals without any rotation: the old key is dropped too.
+    tokens
+        .rotate_if_due(start + Duration::from_secs(200))
+        .unwrap();
+    assert!(tokens.validate(&client(), &token).is_none());
+}
@@ -0,0 +1,50 @@
+#![cfg(windows)]
+
+use std::io;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+use quic_echo::transport::{wsa_startup, DatagramTransport, WinSockTransport};
+
+#[test]
+fn connection_reset_is_reported_once() {
+    wsa_startup().unwrap();
+    let mut transport = WinSockTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = transport.local_addr().unwrap();
+
+    // A receive is pending when the ICMP port unreachable arrives.
+    let mut buf = [0; 64];
+    let err = transport.recv_from(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+    let closed = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    transport.send_to(b"ping", closed).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+    peer.send_to(b"pong", addr).unwrap();
+
+    let mut resets = 0;
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let (read, from) = loop {
+        assert!(Instant::now() < deadline, "datagram not received");
+        match transport.recv_from(&mut buf) {
+            Ok(v) => break v,
+
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => resets += 1,
+
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            Err(e) => panic!("recv_from() failed: {}", e),
+        }
+    };
+
+    assert!(resets <= 1, "{} resets reported", resets);
+    assert_eq!(&buf[..read], b"pong");
+    assert_eq!(from, peer.local_addr().unwrap());
+}